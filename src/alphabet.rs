@@ -0,0 +1,150 @@
+/*!
+The alphabet maps letters to the five-bit letter codes
+stored in a [Word](crate::words::Word). A letter may be a
+single character or a multi-character grapheme such as Welsh
+`ll`. Letter codes are assigned in the order the letters are
+listed, which is also the order words sort in.
+
+There is one alphabet for the whole run. It defaults to
+`a`..`z`, and may be replaced once at startup with
+[Alphabet::install()]. An alphabet file lists one letter per
+line. A line may give several spellings for the same letter,
+separated by whitespace; the first is used for output. Blank
+lines and lines starting with `#` are ignored.
+
+    # Spanish
+    a
+    b
+    ...
+    n
+    ñ
+    ...
+*/
+
+use std::sync::OnceLock;
+
+use anyhow::{Error, bail};
+
+/// Letter codes are five bits.
+pub const MAX_LETTERS: usize = 32;
+
+/// The installed alphabet.
+static ALPHABET: OnceLock<Alphabet> = OnceLock::new();
+
+/// A set of letters with their codes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    /// Spellings for each letter code, canonical first.
+    letters: Vec<Vec<String>>,
+}
+
+impl Default for Alphabet {
+    /// The English alphabet `a`..`z`.
+    fn default() -> Self {
+        let letters = ('a'..='z').map(|c| vec![c.to_string()]).collect();
+        Self { letters }
+    }
+}
+
+impl Alphabet {
+    /// Parse an alphabet file.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut letters: Vec<Vec<String>> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let spellings: Vec<String> = line
+                .split_whitespace()
+                .map(|s| s.to_lowercase())
+                .collect();
+            for s in &spellings {
+                if s.contains('.') {
+                    bail!("{s}: `.` is reserved for blanks");
+                }
+                if letters.iter().flatten().any(|t| t == s) {
+                    bail!("{s}: letter listed twice");
+                }
+            }
+            letters.push(spellings);
+        }
+
+        if letters.is_empty() {
+            bail!("empty alphabet");
+        }
+        if letters.len() > MAX_LETTERS {
+            bail!("alphabet has {} letters, max {MAX_LETTERS}", letters.len());
+        }
+        Ok(Self { letters })
+    }
+
+    /// Make this the alphabet for the rest of the run. This
+    /// must happen before any words are made.
+    pub fn install(self) -> Result<(), Error> {
+        if ALPHABET.set(self).is_err() {
+            bail!("alphabet already in use");
+        }
+        Ok(())
+    }
+
+    /// The alphabet in use.
+    pub fn current() -> &'static Alphabet {
+        ALPHABET.get_or_init(Alphabet::default)
+    }
+
+    /// Number of letters in the alphabet.
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    /// Canonical spelling of the letter with the given code.
+    pub fn letter(&self, code: u8) -> &str {
+        &self.letters[code as usize][0]
+    }
+
+    /// Split a string into the [Word](crate::words::Word)
+    /// bitfields of its letters, with `.` for blank. Letters
+    /// are matched greedily, longest spelling first. Returns
+    /// [None] if some part of the string is not a letter.
+    pub fn bits(&self, word: &str) -> Option<Vec<u8>> {
+        let mut result = Vec::with_capacity(5);
+        let mut rest = word;
+        while !rest.is_empty() {
+            let letter = self
+                .letters
+                .iter()
+                .enumerate()
+                .flat_map(|(code, ss)| ss.iter().map(move |s| (code, s)))
+                .filter(|(_, s)| rest.starts_with(s.as_str()))
+                .max_by_key(|(_, s)| s.len());
+            if let Some((code, s)) = letter {
+                result.push(0x20 | code as u8);
+                rest = &rest[s.len()..];
+            } else if let Some(r) = rest.strip_prefix('.') {
+                result.push(0);
+                rest = r;
+            } else {
+                return None;
+            }
+        }
+        Some(result)
+    }
+}
+
+#[test]
+fn test_alphabet() {
+    let english = Alphabet::default();
+    assert_eq!(english.len(), 26);
+    assert_eq!(english.bits("ab.z"), Some(vec![0x20, 0x21, 0, 0x39]));
+    assert_eq!(english.bits("añb"), None);
+
+    let welsh = Alphabet::parse("# part of Welsh\na\nc\nch\nl\nll\nÑ n~\n").unwrap();
+    assert_eq!(welsh.len(), 6);
+    assert_eq!(welsh.bits("llach"), Some(vec![0x24, 0x20, 0x22]));
+    assert_eq!(welsh.bits("n~ñ.c"), Some(vec![0x25, 0x25, 0, 0x21]));
+    assert_eq!(welsh.letter(5), "ñ");
+
+    assert!(Alphabet::parse("a\nb\na\n").is_err());
+    assert!(Alphabet::parse("a\n.\n").is_err());
+}
@@ -1,11 +1,34 @@
 //! Application state structure used in the search. Also,
 //! command-line argument parsing.
 
+use crate::budget::*;
+use crate::binary::*;
+use crate::build::*;
+use crate::crossword::*;
+use crate::delta::*;
+use crate::explain::*;
+use crate::metadata::*;
+use crate::prune::*;
+use crate::puzzle::*;
+use crate::progress::*;
+use crate::rate::*;
+use crate::render::*;
+use crate::results::*;
+use crate::rpc::*;
+use crate::serve::*;
+use crate::shard::*;
+use crate::stats::*;
+use crate::work::*;
+
+use std::ops::Range;
 use std::str::FromStr;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
 
 use anyhow::{Error, bail};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Used for progress traces.
 #[derive(Debug, Clone, Copy)]
@@ -13,6 +36,8 @@ pub enum TraceStyle {
     None,
     Short,
     Full,
+    Status,
+    Json { solutions: bool },
 }
 
 /// The argument parser needs to know names for the trace
@@ -25,34 +50,223 @@ impl FromStr for TraceStyle {
             "none" => Ok(TraceStyle::None),
             "short" => Ok(TraceStyle::Short),
             "full" => Ok(TraceStyle::Full),
+            "status" => Ok(TraceStyle::Status),
+            "json" => Ok(TraceStyle::Json { solutions: false }),
+            "json-solutions" => Ok(TraceStyle::Json { solutions: true }),
             s => bail!("{s}: unknown trace style"),
         }
     }
 }
 
+/// Reasons a search may stop before it is done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// Enough solutions were found.
+    Limit,
+    /// The deadline passed.
+    TimedOut,
+    /// The node budget ran out.
+    MaxNodes,
+    /// The search was cancelled.
+    Cancelled,
+}
+
+impl Stop {
+    /// Name for machine-readable output.
+    pub fn key(self) -> &'static str {
+        match self {
+            Stop::Limit => "limit",
+            Stop::TimedOut => "timeout",
+            Stop::MaxNodes => "max_nodes",
+            Stop::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::fmt::Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Stop::Limit => "solution limit",
+            Stop::TimedOut => "timeout",
+            Stop::MaxNodes => "node budget",
+            Stop::Cancelled => "cancelled",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+/// Parse a duration such as `30s`, `5m`, `2h` or `500ms`. A
+/// bare number is seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, Error> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let Ok(number) = number.parse::<f64>() else {
+        bail!("{text}: expected a time such as 30s");
+    };
+    let scale = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => bail!("{text}: unknown time unit {unit}"),
+    };
+    Ok(Duration::try_from_secs_f64(number * scale)?)
+}
+
+/// Commands other than the default search. Options given
+/// before the command apply to it too.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Make a uniquely solvable puzzle from a square.
+    Puzzle(PuzzleArgs),
+    /// Rate the difficulty of puzzles.
+    Rate(RateArgs),
+    /// Build a square interactively.
+    Build(BuildArgs),
+    /// Explain why a template has no completions.
+    Explain(ExplainArgs),
+    /// Report word statistics for saved squares.
+    Stats(StatsArgs),
+    /// Drop dictionary words that can be in no square.
+    Prune(PruneArgs),
+    /// Update saved squares for a changed dictionary.
+    Delta(DeltaArgs),
+    /// Merge saved squares, such as the output of shards.
+    Merge(MergeArgs),
+    /// Answer queries over HTTP.
+    Serve(ServeArgs),
+    /// Answer JSON-RPC requests on standard input.
+    Rpc(RpcArgs),
+    /// Convert saved squares to another format.
+    Convert(ConvertArgs),
+    /// Draw saved squares as SVG or an HTML gallery.
+    Render(RenderArgs),
+    /// Export a square or puzzle as ipuz or .puz.
+    Export(ExportArgs),
+    /// Check saved squares and their metadata.
+    Verify(VerifyArgs),
+    /// Hand out search work to workers.
+    ServeWork(ServeWorkArgs),
+    /// Search work handed out by a coordinator.
+    Worker(WorkerArgs),
+}
+
 /// The command-line argument struct.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[arg(short, long, help="maximum number of squares to produce")]
-    limit: Option<usize>,
+    pub limit: Option<usize>,
     #[arg(
         short='p',
         long="progress",
         default_value="none",
-        help="progress trace style (none, short, full)",
+        help="progress trace style (none, short, full, status, json, json-solutions)",
     )]
     trace: TraceStyle,
-    #[arg(short, long, help="allow the same word two or more times in a square")]
-    doubled: bool,
+    #[arg(
+        short,
+        long,
+        global=true,
+        help="allow the same word two or more times in a square",
+    )]
+    pub doubled: bool,
     #[arg(short, long, help="produce both canonical squares and their transposions")]
     transposed: bool,
-    #[arg(short, long, default_value="strings.json", help="output file")]
+    #[arg(
+        long,
+        global=true,
+        overrides_with="lenient",
+        help="fail if any dictionary line is unusable (default)",
+    )]
+    pub strict: bool,
+    #[arg(
+        long,
+        global=true,
+        overrides_with="strict",
+        help="skip unusable dictionary lines, with a report",
+    )]
+    pub lenient: bool,
+    #[arg(short, long, default_value="squares.json", help="output file, or - for standard output")]
     pub output: PathBuf,
+    #[arg(
+        long,
+        global=true,
+        help="output format: json, ndjson, txt, csv or bin (default from the output file name)",
+    )]
+    pub format: Option<Format>,
+    #[arg(long, global=true, help="save run metadata with the squares")]
+    pub metadata: bool,
+    #[arg(long, help="save word statistics to this file: .csv, .json or text")]
+    pub stats: Option<PathBuf>,
+    #[arg(long, global=true, help="alphabet definition file (default a-z)")]
+    pub alphabet: Option<PathBuf>,
+    #[arg(
+        long,
+        global=true,
+        help="separate dictionary for columns; rows use the main dictionary",
+    )]
+    pub columns: Option<PathBuf>,
+    #[arg(long, help="starting grid: five lines with `.` for blanks")]
+    pub template: Option<PathBuf>,
+    #[arg(long, global=true, help="file of per-position word constraints")]
+    pub constraints: Option<PathBuf>,
+    #[arg(
+        long,
+        global=true,
+        help="first drop dictionary words that can be in no square",
+    )]
+    pub prune: bool,
+    #[arg(
+        long,
+        conflicts_with="template",
+        help="search only part I of N of the first-row words, given as I/N",
+    )]
+    pub shard: Option<Shard>,
+    #[arg(long, value_parser=parse_duration, help="stop searching after this long, such as 30s")]
+    pub timeout: Option<Duration>,
+    #[arg(long, help="stop searching after this many nodes")]
+    pub max_nodes: Option<usize>,
+    #[arg(long, help="letters the square must be made of, such as `a3 b2 c ...`")]
+    pub letters: Option<String>,
+    #[arg(long, requires="letters", help="letters are an upper limit, not an exact set")]
+    pub at_most: bool,
+    #[arg(
+        long,
+        default_value_t=0,
+        help="allow up to this many positions that are not words",
+    )]
+    pub max_misses: usize,
     #[arg(help="dictionary", default_value="usa_5.txt")]
     pub dict: PathBuf,
 }
 
+impl Args {
+    /// The search settings given, for machine-readable
+    /// output. Paths are as given.
+    pub fn settings(&self) -> serde_json::Value {
+        serde_json::json!({
+            "doubled": self.doubled,
+            "transposed": self.transposed,
+            "limit": self.limit,
+            "max_misses": self.max_misses,
+            "timeout": self.timeout.map(|t| t.as_secs_f64()),
+            "max_nodes": self.max_nodes,
+            "shard": self.shard.map(|s| s.to_string()),
+            "template": self.template,
+            "columns": self.columns,
+            "constraints": self.constraints,
+            "prune": self.prune,
+            "letters": self.letters,
+            "at_most": self.at_most,
+        })
+    }
+}
+
 /// The app state. Contains things needed during search.
 pub struct AppState {
     /// Number of nodes searched.
@@ -65,22 +279,65 @@ pub struct AppState {
     pub doubled: bool,
     /// Allow transposed squares.
     pub transposed: bool,
+    /// Letters still available to place, if limited.
+    pub budget: Option<LetterBudget>,
+    /// Number of positions allowed to be non-words.
+    pub max_misses: usize,
+    /// First-row words to try, as indices into the row 0
+    /// dictionary, if not all.
+    pub first_rows: Option<Range<usize>>,
+    /// Time to give up searching, if any.
+    pub deadline: Option<Instant>,
+    /// Number of nodes to give up searching after, if any.
+    pub max_nodes: Option<usize>,
+    /// Index in the row 0 dictionary of the first-row word
+    /// being searched under, when searching from scratch.
+    pub first_row: Option<usize>,
+    /// Position of the first word placed when a near-miss
+    /// search starts past the first row: every position
+    /// before it is a miss.
+    pub seed: usize,
+    /// Progress through the search, if reported.
+    pub progress: Option<Progress>,
+    /// Set from elsewhere to stop the search.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Why the search stopped early, if it did.
+    pub stop: Option<Stop>,
 }
 
 #[cfg(test)]
 impl Default for AppState {
     fn default() -> Self {
+        let mut state = Self::solver(false, 1000);
+        state.transposed = false;
+        state
+    }
+}
+
+impl AppState {
+    /// Set up a quiet state for finding up to `limit`
+    /// completions of a template. Transposed completions
+    /// are different completions.
+    pub fn solver(doubled: bool, limit: usize) -> Self {
         Self {
             nodes: 0,
-            limit: Some(1000),
+            limit: Some(limit),
             trace: TraceStyle::None,
-            doubled: false,
-            transposed: false,
+            doubled,
+            transposed: true,
+            budget: None,
+            max_misses: 0,
+            first_rows: None,
+            deadline: None,
+            max_nodes: None,
+            first_row: None,
+            seed: 0,
+            progress: None,
+            cancel: None,
+            stop: None,
         }
     }
-}
 
-impl AppState {
     /// Given the arguments, set up the state.
     pub fn new(args: &Args) -> Self {
         Self {
@@ -88,7 +345,48 @@ impl AppState {
             limit: args.limit,
             trace: args.trace,
             doubled: args.doubled,
-            transposed: args.transposed,
+            // With different dictionaries for rows and
+            // columns, or with a template or constraints,
+            // the transpose of a solution is not a
+            // solution, so canonicity means nothing.
+            transposed: args.transposed
+                || args.columns.is_some()
+                || args.template.is_some()
+                || args.constraints.is_some(),
+            // Set up after the alphabet is known: see
+            // [AppState::for_search()].
+            budget: None,
+            max_misses: args.max_misses,
+            // Set up once the dictionaries are loaded.
+            first_rows: None,
+            deadline: args.timeout.map(|t| Instant::now() + t),
+            max_nodes: args.max_nodes,
+            first_row: None,
+            seed: 0,
+            progress: None,
+            cancel: None,
+            stop: None,
         }
     }
+
+    /// Set up the state for a search, with the letter
+    /// budget the arguments call for. The alphabet must be
+    /// installed first.
+    pub fn for_search(args: &Args) -> Result<Self, Error> {
+        let mut state = Self::new(args);
+        if let Some(ref letters) = args.letters {
+            state.budget = Some(LetterBudget::parse(letters, !args.at_most)?);
+        }
+        Ok(state)
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+    assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+    assert_eq!(parse_duration("2").unwrap(), Duration::from_secs(2));
+    assert!(parse_duration("2d").is_err());
+    assert!(parse_duration("s").is_err());
 }
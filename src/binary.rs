@@ -0,0 +1,250 @@
+/*!
+A compact binary form for large sets of squares. Each row
+is saved as its index in the sorted list of row words of
+the dictionaries, so a square takes ten bytes with a
+dictionary of fewer than 65535 words. The list depends on the
+dictionaries, so the file starts with a hash of it, and
+reading it back needs the same dictionaries.
+
+All numbers are little-endian:
+
+* the magic bytes `WS5B` and a version byte, 1;
+* the index width in bytes, from 2 to 4;
+* the hash of the row word list (64 bits) and its length
+  (32 bits);
+* the length of the metadata (32 bits) and the metadata as
+  JSON, if any;
+* the number of squares (64 bits);
+* for each square, the index of each of its five rows. The
+  largest index of the width is an escape, for a row that
+  is not a word: it is followed by the row as a raw 32-bit
+  word.
+
+`ws5 convert` converts saved squares from one format to
+another.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::metadata::*;
+use crate::results::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Error, anyhow, bail};
+
+/// Start of a binary file.
+pub const MAGIC: &[u8] = b"WS5B";
+
+/// Version of the binary format.
+const VERSION: u8 = 1;
+
+/// The sorted row words of `dicts`, which rows are saved
+/// as indices into.
+fn row_words(dicts: &Dicts) -> Vec<Word> {
+    let mut words: Vec<Word> = (0..5).flat_map(|p| dicts.get(p).into_iter().copied()).collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Hash of a word list: 64-bit FNV-1a of the words.
+fn words_hash(words: &[Word]) -> u64 {
+    words
+        .iter()
+        .flat_map(|w| w.0.to_le_bytes())
+        .fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Bytes needed per index into `count` words, leaving room
+/// for the escape.
+fn index_width(count: usize) -> usize {
+    (2..4).find(|&w| count < (1 << (8 * w)) - 1).unwrap_or(4)
+}
+
+/// Save squares in binary form. `save` should be buffered:
+/// each index is written as it is made.
+pub fn write_binary(
+    save: &mut dyn Write,
+    squares: &[Square],
+    dicts: &Dicts,
+    metadata: Option<&Metadata>,
+) -> Result<(), Error> {
+    let words = row_words(dicts);
+    let index: HashMap<Word, u32> = words.iter().enumerate().map(|(i, &w)| (w, i as u32)).collect();
+    let width = index_width(words.len());
+    let escape = u32::MAX >> (8 * (4 - width));
+
+    save.write_all(MAGIC)?;
+    save.write_all(&[VERSION, width as u8])?;
+    save.write_all(&words_hash(&words).to_le_bytes())?;
+    save.write_all(&(words.len() as u32).to_le_bytes())?;
+    let metadata = match metadata {
+        Some(metadata) => serde_json::to_vec(metadata)?,
+        None => Vec::new(),
+    };
+    save.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    save.write_all(&metadata)?;
+    save.write_all(&(squares.len() as u64).to_le_bytes())?;
+
+    for s in squares {
+        for p in 0..5 {
+            let row = s.get_pos(p);
+            match index.get(&row) {
+                Some(&i) => save.write_all(&i.to_le_bytes()[..width])?,
+                None => {
+                    save.write_all(&escape.to_le_bytes()[..width])?;
+                    save.write_all(&row.0.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads numbers from bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            bail!("file is cut short");
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Read an unsigned number of `n` bytes.
+    fn number(&mut self, n: usize) -> Result<u64, Error> {
+        let mut bytes = [0; 8];
+        bytes[..n].copy_from_slice(self.take(n)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+/// True if `bytes` are squares in binary form.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Read squares in binary form, saved with the same
+/// dictionaries as `dicts`.
+pub fn parse_binary(bytes: &[u8], dicts: &Dicts) -> Result<Results, Error> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        bail!("not a binary squares file");
+    }
+    let version = reader.number(1)? as u8;
+    if version != VERSION {
+        bail!("binary format version {version} is not supported");
+    }
+    let width = reader.number(1)? as usize;
+    if !(2..=4).contains(&width) {
+        bail!("bad index width {width}");
+    }
+
+    let words = row_words(dicts);
+    let (hash, count) = (reader.number(8)?, reader.number(4)? as usize);
+    if hash != words_hash(&words) || count != words.len() {
+        bail!("saved with different dictionaries");
+    }
+
+    let length = reader.number(4)? as usize;
+    let metadata = match length {
+        0 => None,
+        n => Some(serde_json::from_slice(reader.take(n)?).map_err(|e| anyhow!("metadata: {e}"))?),
+    };
+
+    let escape = u32::MAX >> (8 * (4 - width));
+    let total = reader.number(8)?;
+    let mut squares = Vec::new();
+    for i in 0..total {
+        let mut rows = [Word::default(); 5];
+        for row in &mut rows {
+            let index = reader.number(width)? as u32;
+            *row = if index == escape {
+                let word = Word(reader.number(4)? as u32);
+                if !word.is_valid() {
+                    bail!("square {i}: bad word {:#010x}", word.0);
+                }
+                word
+            } else {
+                *words
+                    .get(index as usize)
+                    .ok_or_else(|| anyhow!("square {i}: bad word index {index}"))?
+            };
+        }
+        squares.push(Square::from_words(rows));
+    }
+    if !reader.bytes.is_empty() {
+        bail!("extra bytes after the squares");
+    }
+    Ok(Results { squares, metadata })
+}
+
+/// Arguments for the `convert` command.
+#[derive(clap::Args)]
+pub struct ConvertArgs {
+    #[arg(help="saved squares to convert")]
+    pub input: PathBuf,
+    #[arg(help="file to save the squares to, in the format its name or --format calls for")]
+    pub output: PathBuf,
+}
+
+impl ConvertArgs {
+    /// Run the `convert` command: save squares in another
+    /// format, keeping their order, near-miss marks and
+    /// metadata.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let results = read_results(&self.input, dicts)?;
+        let near_misses = results.squares.iter().any(|s| !misses(s, dicts).is_empty());
+        let metadata = results.metadata.as_ref();
+        write_squares(&self.output, args.format, &results.squares, dicts, near_misses, metadata)?;
+        summary(&self.output, format!("{} squares", results.squares.len()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_binary() {
+    let square = Square::test();
+    // Leave out the last row word, so that it must be
+    // escaped.
+    let mut words = square.words();
+    words.remove(4);
+    let dicts = Dicts::from(Dict::from_words(&words));
+    let mut other = square.clone();
+    other.set_pos(0, square.get_pos(1));
+    let squares = [square, other];
+
+    assert_eq!(index_width(100), 2);
+    assert_eq!(index_width(65535), 3);
+    assert_eq!(index_width(1 << 24), 4);
+
+    let mut bytes = Vec::new();
+    write_binary(&mut bytes, &squares, &dicts, None).unwrap();
+    assert!(is_binary(&bytes));
+    // Header, then ten 2-byte indices, with the last row of
+    // each square escaped and followed by its raw word.
+    assert_eq!(bytes.len(), 30 + 10 * 2 + 2 * 4);
+    let results = parse_binary(&bytes, &dicts).unwrap();
+    assert_eq!(results.squares, squares);
+    assert!(results.metadata.is_none());
+
+    assert!(parse_binary(&bytes[..bytes.len() - 1], &dicts).is_err());
+    // An escaped word with a field that is no letter.
+    let mut bad = bytes.clone();
+    let at = bad.len() - 4;
+    bad[at..].copy_from_slice(&0x3fffffffu32.to_le_bytes());
+    assert!(parse_binary(&bad, &dicts).is_err());
+    let other_dicts = Dicts::from(Dict::from_words(&words[1..]));
+    assert!(parse_binary(&bytes, &other_dicts).is_err());
+}
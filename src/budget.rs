@@ -0,0 +1,115 @@
+/*!
+Letter budgets limit how many times each letter may appear
+in a square, as when building a square from a given set of
+tiles. The budget is charged as words are placed and
+refunded as they are removed, so that the search can prune
+any placement that overspends.
+*/
+
+use crate::alphabet::*;
+use crate::words::*;
+
+use anyhow::{Error, bail};
+
+/// Remaining letter counts.
+#[derive(Debug, Clone)]
+pub struct LetterBudget {
+    /// Remaining count for each letter code.
+    remaining: [u32; MAX_LETTERS],
+}
+
+impl LetterBudget {
+    /// Parse a letter multiset. Whitespace is ignored. A
+    /// single letter followed by a number stands for that
+    /// many copies of the letter: `e12 a9 b2` is the same as
+    /// twelve `e`s, nine `a`s and two `b`s. If `exact` is
+    /// set, the budget must be exactly the 25 letters of a
+    /// square; otherwise it is an upper limit.
+    pub fn parse(letters: &str, exact: bool) -> Result<Self, Error> {
+        let alphabet = Alphabet::current();
+        let mut remaining = [0; MAX_LETTERS];
+        for token in letters.split_whitespace() {
+            let split = token.find(|c: char| c.is_ascii_digit()).unwrap_or(token.len());
+            let (letters, count) = token.split_at(split);
+            let Some(bits) = alphabet.bits(letters) else {
+                bail!("{letters}: invalid letters");
+            };
+            if bits.contains(&0) {
+                bail!("{letters}: blanks are not letters");
+            }
+            let count = if count.is_empty() {
+                1
+            } else if bits.len() == 1 {
+                count.parse()?
+            } else {
+                bail!("{token}: a count must follow a single letter");
+            };
+            for b in bits {
+                remaining[(b & 0x1f) as usize] += count;
+            }
+        }
+
+        let total: u32 = remaining.iter().sum();
+        if exact && total != 25 {
+            bail!("{total} letters given, need exactly 25");
+        }
+        Ok(Self { remaining })
+    }
+
+    /// Charge the budget for the letters that `word` adds to
+    /// the cells left blank in `target`. If the budget
+    /// cannot cover them, charge nothing and return [false].
+    pub fn take(&mut self, target: Word, word: Word) -> bool {
+        let mut needed = [0; MAX_LETTERS];
+        for (t, w) in target.bits().zip(word.bits()) {
+            if t == 0 && w != 0 {
+                let code = (w & 0x1f) as usize;
+                needed[code] += 1;
+                if needed[code] > self.remaining[code] {
+                    return false;
+                }
+            }
+        }
+        for (r, n) in self.remaining.iter_mut().zip(needed) {
+            *r -= n;
+        }
+        true
+    }
+
+    /// Refund the budget for a placement charged by
+    /// [LetterBudget::take()].
+    pub fn give_back(&mut self, target: Word, word: Word) {
+        for (t, w) in target.bits().zip(word.bits()) {
+            if t == 0 && w != 0 {
+                self.remaining[(w & 0x1f) as usize] += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_budget() {
+    assert!(LetterBudget::parse("abcde", true).is_err());
+    assert!(LetterBudget::parse("ab3", false).is_err());
+    // A blank would otherwise be counted as the letter
+    // with code 0.
+    assert!(LetterBudget::parse("ab.", false).is_err());
+    assert!(LetterBudget::parse(".3", false).is_err());
+    let exact = "aaaaa bbbbb ccccc d5 e5";
+    assert!(LetterBudget::parse(exact, true).is_ok());
+
+    let mut budget = LetterBudget::parse("a2 b c d e", false).unwrap();
+    let empty = Word::default();
+    let abcde = Word::from_str("abcde").unwrap();
+    let aabcd = Word::from_str("aabcd").unwrap();
+    assert!(budget.take(empty, abcde));
+    assert!(!budget.take(empty, aabcd));
+
+    // Only the blank cells of the target are charged.
+    let a = Word::from_str("a....").unwrap();
+    assert!(budget.take(a, Word::from_str("aa...").unwrap()));
+    assert!(!budget.take(a, Word::from_str("ab...").unwrap()));
+
+    budget.give_back(empty, abcde);
+    assert!(budget.take(empty, Word::from_str("bcde.").unwrap()));
+}
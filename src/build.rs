@@ -0,0 +1,281 @@
+/*!
+Interactive square building. The user places words by hand
+and the engine helps: after each change it shows how many
+words match each open position, and flags positions that
+can no longer be filled. It can list candidate words for a
+position, and complete the square from the current grid.
+Every change can be undone and redone.
+
+Commands are read a line at a time:
+
+    r2 crane     place a word (or pattern, with `.`) in a position
+    clear c3     blank out a position
+    cand r4 [N]  list up to N candidate words for a position
+    complete [N] fill in the square; with N, list N completions
+    undo, redo   step back and forth through changes
+    show         show the grid again
+    help         list commands
+    quit         leave
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+use anyhow::{Error, bail};
+
+/// Default number of candidates to list.
+const CANDIDATES: usize = 20;
+
+/// Arguments for the `build` command.
+#[derive(clap::Args)]
+pub struct BuildArgs {
+    #[arg(help="starting grid: five lines with `.` for blanks")]
+    pub start: Option<PathBuf>,
+}
+
+/// Parse a position such as `r2` or `c3`.
+pub fn parse_pos(name: &str) -> Result<usize, Error> {
+    let (offset, index) = if let Some(i) = name.strip_prefix('r') {
+        (0, i)
+    } else if let Some(i) = name.strip_prefix('c') {
+        (5, i)
+    } else {
+        bail!("{name}: expected position such as r2 or c3");
+    };
+    match index.parse::<usize>() {
+        Ok(i) if i < 5 => Ok(offset + i),
+        _ => bail!("{name}: position out of range"),
+    }
+}
+
+/// The state of an interactive session.
+pub struct Builder<'a> {
+    /// The grid being built.
+    grid: Square,
+    /// Earlier grids, for undo.
+    undo: Vec<Square>,
+    /// Undone grids, for redo.
+    redo: Vec<Square>,
+    /// The dictionaries to build from.
+    dicts: &'a Dicts,
+    /// Search settings for checking and completing.
+    state: AppState,
+}
+
+impl<'a> Builder<'a> {
+    /// Start building from the given grid.
+    pub fn new(grid: Square, dicts: &'a Dicts, doubled: bool) -> Self {
+        let state = AppState::solver(doubled, 1);
+        Self { grid, undo: Vec::new(), redo: Vec::new(), dicts, state }
+    }
+
+    /// Replace the grid, remembering the old one for undo.
+    fn change(&mut self, grid: Square) {
+        let old = std::mem::replace(&mut self.grid, grid);
+        self.undo.push(old);
+        self.redo.clear();
+    }
+
+    /// Find the first `n` completions of the grid.
+    fn completions(&mut self, n: usize) -> Vec<Square> {
+        self.state.limit = Some(n);
+        let mut results = Vec::new();
+        self.state.find_completions(&self.grid, self.dicts, &mut results);
+        results
+    }
+
+    /// Show the grid, the match counts of the open
+    /// positions, and any dead ends.
+    pub fn show(&self, out: &mut impl Write) -> Result<(), Error> {
+        writeln!(out, "{}", self.grid.as_string())?;
+
+        let open: Vec<String> = (0..10)
+            .filter_map(|p| {
+                let target = self.grid.get_pos(p);
+                if target.is_empty() || target.is_full() {
+                    return None;
+                }
+                let count = self.dicts.get(p).match_count(target);
+                Some(format!("{} {count}", pos_name(p)))
+            })
+            .collect();
+        if !open.is_empty() {
+            writeln!(out, "open: {}", open.join(", "))?;
+        }
+
+        for p in misses(&self.grid, self.dicts) {
+            let word = self.grid.get_pos(p);
+            if word.is_full() {
+                writeln!(out, "dead end: {} {word} is not a word", pos_name(p))?;
+            } else {
+                writeln!(out, "dead end: {} {word} has no candidates", pos_name(p))?;
+            }
+        }
+        if !self.state.doubled && self.grid.has_double() {
+            writeln!(out, "dead end: repeated word")?;
+        }
+        Ok(())
+    }
+
+    /// Run one command line, writing any output. Returns
+    /// [false] when the user is done.
+    pub fn command(&mut self, line: &str, out: &mut impl Write) -> Result<bool, Error> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let count = |i: usize, default: usize| -> Result<usize, Error> {
+            Ok(fields.get(i).map(|n| n.parse()).transpose()?.unwrap_or(default))
+        };
+
+        match fields[..] {
+            [] => return Ok(true),
+            ["quit"] | ["q"] => return Ok(false),
+            ["help"] | ["?"] => {
+                writeln!(out, "r2 WORD, clear c3, cand r4 [N], complete [N], undo, redo, show, quit")?;
+                return Ok(true);
+            }
+            ["show"] => (),
+            ["undo"] => match self.undo.pop() {
+                Some(grid) => self.redo.push(std::mem::replace(&mut self.grid, grid)),
+                None => bail!("nothing to undo"),
+            },
+            ["redo"] => match self.redo.pop() {
+                Some(grid) => self.undo.push(std::mem::replace(&mut self.grid, grid)),
+                None => bail!("nothing to redo"),
+            },
+            ["clear", pos] => {
+                let mut grid = self.grid.clone();
+                grid.set_pos(parse_pos(pos)?, Word::default());
+                self.change(grid);
+            }
+            ["cand", pos, ..] => {
+                let p = parse_pos(pos)?;
+                let n = count(2, CANDIDATES)?;
+                let target = self.grid.get_pos(p);
+                if target.is_empty() {
+                    bail!("{} is blank: everything fits", pos_name(p));
+                }
+                let cands: Vec<String> = self
+                    .dicts
+                    .get(p)
+                    .matches(target)
+                    .filter(|&w| {
+                        let mut grid = self.grid.clone();
+                        grid.set_pos(p, w);
+                        self.state.cross_fit(&grid, self.dicts, p)
+                    })
+                    .take(n)
+                    .map(|w| w.as_string())
+                    .collect();
+                if cands.is_empty() {
+                    writeln!(out, "no candidates")?;
+                } else {
+                    writeln!(out, "{}", cands.join(" "))?;
+                }
+                return Ok(true);
+            }
+            ["complete"] => {
+                let Some(grid) = self.completions(1).pop() else {
+                    bail!("no completions");
+                };
+                self.change(grid);
+            }
+            ["complete", _] => {
+                let results = self.completions(count(1, 1)?);
+                if results.is_empty() {
+                    writeln!(out, "no completions")?;
+                }
+                for s in results {
+                    writeln!(out, "{}\n", s.as_string())?;
+                }
+                return Ok(true);
+            }
+            [pos, word] => {
+                let p = parse_pos(pos)?;
+                let word = Word::from_str(word)?;
+                let target = self.grid.get_pos(p);
+                if !target.is_fit(word) {
+                    bail!("{word} does not fit {} {target}", pos_name(p));
+                }
+                // Keep the letters already there.
+                let merged = Word(target.0 | word.0);
+                let mut grid = self.grid.clone();
+                grid.set_pos(p, merged);
+                self.change(grid);
+            }
+            _ => bail!("unknown command: try help"),
+        }
+
+        self.show(out)?;
+        Ok(true)
+    }
+}
+
+impl BuildArgs {
+    /// Run the `build` command: an interactive session on
+    /// the terminal.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let grid = match self.start {
+            Some(ref path) => read_square(path)?,
+            None => Square::default(),
+        };
+        let mut builder = Builder::new(grid, dicts, args.doubled);
+
+        let stdin = std::io::stdin();
+        let interactive = stdin.is_terminal();
+        let mut out = std::io::stdout();
+        builder.show(&mut out)?;
+        loop {
+            if interactive {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                break;
+            }
+            match builder.command(&line, &mut out) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(e) => writeln!(out, "{e}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_builder() {
+    let square = Square::test();
+    let dicts = Dicts::test(&[]);
+    let mut builder = Builder::new(Square::default(), &dicts, false);
+    let mut out = Vec::new();
+
+    assert!(builder.command("r0 abcde", &mut out).unwrap());
+    assert!(builder.command("c4 zzjoy", &mut out).is_err());
+    assert!(builder.command("c4 ....y", &mut out).unwrap());
+    assert_eq!(builder.grid.get_pos(9), Word::from_str("e...y").unwrap());
+
+    out.clear();
+    builder.command("cand c0", &mut out).unwrap();
+    assert_eq!(String::from_utf8_lossy(&out), "afkpu\n");
+
+    builder.command("complete", &mut out).unwrap();
+    assert_eq!(builder.grid, square);
+
+    builder.command("undo", &mut out).unwrap();
+    builder.command("undo", &mut out).unwrap();
+    assert_eq!(builder.grid.get_pos(9), Word::from_str("e....").unwrap());
+    builder.command("redo", &mut out).unwrap();
+    assert_eq!(builder.grid.get_pos(9), Word::from_str("e...y").unwrap());
+
+    out.clear();
+    builder.command("r2 klmnz", &mut out).unwrap();
+    assert!(String::from_utf8_lossy(&out).contains("dead end: col 4"));
+    assert!(!builder.command("quit", &mut out).unwrap());
+}
@@ -0,0 +1,147 @@
+/*!
+Per-position constraints on a square. A constraint file has
+one constraint per line, giving a position and a filter on
+the words allowed there:
+
+    # position  filter
+    row 0       ^s...s$
+    col 2       ^.[aeiou]
+    row 4       @themed.txt
+
+Rows and columns are numbered from 0. A filter is either a
+regular expression that the word must match, or `@` and the
+name of a word list file that the word must appear in. A
+relative word list name is taken relative to the constraint
+file. Several constraints on one position must all hold.
+
+A regular expression is matched against the word as
+written out by [Word::as_string()], not letter by letter.
+With an alphabet of multi-character letters, `.` matches
+one character of a letter such as `ll`, so `^.....$` does
+not match a five-letter word containing `ll`.
+
+Constraints are applied by giving each constrained position
+its own filtered [Dict], so that matching and counting
+during search see only the allowed words.
+*/
+
+use crate::dict::*;
+use crate::loader::*;
+use crate::words::*;
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Error, bail};
+use regex::Regex;
+
+/// A restriction on the words allowed in a position.
+pub enum Filter {
+    /// The word must match the pattern.
+    Pattern(Regex),
+    /// The word must be in the list.
+    List(HashSet<Word>),
+}
+
+impl Filter {
+    /// True if the filter allows the word.
+    pub fn allows(&self, word: Word) -> bool {
+        match self {
+            Filter::Pattern(re) => re.is_match(&word.as_string()),
+            Filter::List(words) => words.contains(&word),
+        }
+    }
+}
+
+/// A filter on the words of one position of the square.
+pub struct Constraint {
+    /// Position in the square: rows are 0..5, columns 5..10.
+    pub pos: usize,
+    /// Restriction on words at the position.
+    pub filter: Filter,
+}
+
+/// Parse a position name such as `row 2` or `col 3`.
+fn parse_pos(kind: &str, index: &str) -> Result<usize, Error> {
+    let offset = match kind {
+        "row" => 0,
+        "col" | "column" => 5,
+        k => bail!("{k}: expected row or col"),
+    };
+    let index: usize = index.parse()?;
+    if index >= 5 {
+        bail!("{index}: position out of range");
+    }
+    Ok(offset + index)
+}
+
+/// Read a word list file for a list filter.
+fn load_list(path: &Path) -> Result<HashSet<Word>, Error> {
+    let text = std::fs::read_to_string(path)?;
+    let (words, report) = load_words(&text);
+    if report.has_errors() {
+        bail!("bad word list\n{}", report.errors(path.display()));
+    }
+    Ok(words.into_iter().collect())
+}
+
+/// Parse the text of a constraint file. Word lists are
+/// found relative to `dir`.
+pub fn parse_constraints(text: &str, dir: &Path) -> Result<Vec<Constraint>, Error> {
+    let mut result = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parse = || {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [kind, index, filter] = fields[..] else {
+                bail!("expected position and filter");
+            };
+            let pos = parse_pos(kind, index)?;
+            let filter = match filter.strip_prefix('@') {
+                Some(name) => Filter::List(load_list(&dir.join(name))?),
+                None => Filter::Pattern(Regex::new(filter)?),
+            };
+            Ok(Constraint { pos, filter })
+        };
+        match parse() {
+            Ok(c) => result.push(c),
+            Err(e) => bail!("line {}: {e}", i + 1),
+        }
+    }
+    Ok(result)
+}
+
+impl Dicts {
+    /// Apply the constraints to the position dictionaries.
+    pub fn apply(&mut self, constraints: &[Constraint]) {
+        for c in constraints {
+            self.constrain(c.pos, |w| c.filter.allows(w));
+        }
+    }
+}
+
+#[test]
+fn test_constraints() {
+    let text = "# test\nrow 0 ^s...s$\n\ncol 2 ^.[aeiou]\n";
+    let constraints = parse_constraints(text, Path::new(".")).unwrap();
+    assert_eq!(constraints.len(), 2);
+    assert_eq!(constraints[1].pos, 7);
+
+    let words = ["sacks", "socks", "sacky", "track"]
+        .map(|w| Word::from_str(w).unwrap());
+    let mut dicts = Dicts::from(Dict::from_words(&words));
+    dicts.apply(&constraints);
+
+    let row0: Vec<String> = dicts.get(0).into_iter().map(|w| w.as_string()).collect();
+    assert_eq!(row0, ["sacks", "socks"]);
+    assert_eq!(dicts.get(7).match_count(Word::from_str("s....").unwrap()), 3);
+    assert_eq!(dicts.get(7).match_count(Word::from_str("t....").unwrap()), 0);
+    assert_eq!(dicts.get(1).into_iter().count(), 4);
+
+    assert!(parse_constraints("row 5 abc\n", Path::new(".")).is_err());
+    assert!(parse_constraints("row 1\n", Path::new(".")).is_err());
+}
@@ -0,0 +1,492 @@
+/*!
+Exporting squares and puzzles for crossword tools, in the
+ipuz JSON format and the Across Lite `.puz` binary format.
+Each export has a solution grid, a fill grid showing any
+given letters, and an empty clue for each of the ten words.
+The grid has no blocks, so the clues are numbered 1, 6, 7,
+8 and 9 across and 1 to 5 down.
+
+`.puz` files hold the grids as one byte per cell, so every
+letter must be a single Latin-1 character. They carry
+checksums of their header, grids and text, which the
+parser here checks.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::squares::*;
+
+use std::path::PathBuf;
+
+use anyhow::{Error, anyhow, bail};
+use serde_json::{Value, json};
+
+/// Clue numbers of the rows and the columns.
+const ACROSS: [usize; 5] = [1, 6, 7, 8, 9];
+const DOWN: [usize; 5] = [1, 2, 3, 4, 5];
+
+/// Magic string of a `.puz` file, and where it goes.
+const PUZ_MAGIC: &[u8] = b"ACROSS&DOWN\0";
+const PUZ_MAGIC_AT: usize = 0x02;
+
+/// Length of a `.puz` header.
+const PUZ_HEADER: usize = 0x34;
+
+/// A crossword made from a square.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crossword {
+    pub title: String,
+    pub author: String,
+    /// The answer.
+    pub solution: Square,
+    /// The given letters, with blanks to fill in.
+    pub fill: Square,
+    /// Clues across and down, in row and column order.
+    pub across: [String; 5],
+    pub down: [String; 5],
+}
+
+/// The clue number of a cell, if any.
+fn cell_number(r: usize, c: usize) -> Option<usize> {
+    match (r, c) {
+        (0, c) => Some(DOWN[c]),
+        (r, 0) => Some(ACROSS[r]),
+        _ => None,
+    }
+}
+
+/// The letters of a grid, uppercased, with `None` for
+/// blanks.
+fn grid_letters(grid: &Square) -> Vec<Vec<Option<String>>> {
+    (0..5)
+        .map(|r| {
+            grid.get_pos(r)
+                .chars()
+                .map(|l| (l != ".").then(|| l.to_uppercase()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Make a grid from uppercased letters, with `None` for
+/// blanks.
+fn grid_from_letters(letters: &[Vec<Option<String>>]) -> Result<Square, Error> {
+    if letters.len() != 5 || letters.iter().any(|row| row.len() != 5) {
+        bail!("grid must be 5×5");
+    }
+    let rows: Vec<String> = letters
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|l| l.as_deref().map_or(".".to_string(), str::to_lowercase))
+                .collect()
+        })
+        .collect();
+    Square::parse(&rows.join("\n"))
+}
+
+/// The `.puz` checksum of `data`, continuing from `sum`.
+pub fn puz_checksum(data: &[u8], sum: u16) -> u16 {
+    data.iter().fold(sum, |sum, &b| {
+        let sum = if sum & 1 != 0 { (sum >> 1) | 0x8000 } else { sum >> 1 };
+        sum.wrapping_add(b as u16)
+    })
+}
+
+/// The `.puz` byte for a letter.
+fn puz_byte(letter: &str) -> Result<u8, Error> {
+    let mut chars = letter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if (c as u32) < 0x100 => Ok(c as u8),
+        _ => bail!("{letter}: .puz letters must be single Latin-1 characters"),
+    }
+}
+
+/// Latin-1 text for a `.puz` file.
+fn puz_text(text: &str) -> Result<Vec<u8>, Error> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).map_err(|_| anyhow!("{text}: not Latin-1 text")))
+        .collect()
+}
+
+/// Checksums of a `.puz` file: of the header, of the file
+/// as a whole, and the masked checksums.
+struct PuzChecksums {
+    header: u16,
+    file: u16,
+    masked: [u8; 8],
+}
+
+impl PuzChecksums {
+    /// Checksum the parts of a `.puz` file: `info` is the
+    /// eight header bytes from the width on, and `strings`
+    /// the title, author, copyright, clues and notes.
+    fn new(info: &[u8], solution: &[u8], fill: &[u8], strings: &[Vec<u8>]) -> Self {
+        let header = puz_checksum(info, 0);
+
+        // The text checksum covers the title, author,
+        // copyright and notes with their terminators, if
+        // not empty, and the clues without.
+        let (title, author, copyright) = (&strings[0], &strings[1], &strings[2]);
+        let (clues, notes) = (&strings[3..strings.len() - 1], &strings[strings.len() - 1]);
+        let text = |mut sum: u16| {
+            for s in [title, author, copyright] {
+                if !s.is_empty() {
+                    sum = puz_checksum(s, sum);
+                    sum = puz_checksum(&[0], sum);
+                }
+            }
+            for clue in clues {
+                sum = puz_checksum(clue, sum);
+            }
+            if !notes.is_empty() {
+                sum = puz_checksum(notes, sum);
+                sum = puz_checksum(&[0], sum);
+            }
+            sum
+        };
+
+        let mut file = puz_checksum(solution, header);
+        file = puz_checksum(fill, file);
+        let file = text(file);
+
+        let sums = [header, puz_checksum(solution, 0), puz_checksum(fill, 0), text(0)];
+        let mut masked = [0; 8];
+        for (i, sum) in sums.iter().enumerate() {
+            masked[i] = b"ICHE"[i] ^ (sum & 0xff) as u8;
+            masked[i + 4] = b"ATED"[i] ^ (sum >> 8) as u8;
+        }
+        Self { header, file, masked }
+    }
+}
+
+impl Crossword {
+    /// Make a crossword with empty clues.
+    pub fn new(solution: &Square, fill: &Square, title: &str, author: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            author: author.to_string(),
+            solution: solution.clone(),
+            fill: fill.clone(),
+            across: Default::default(),
+            down: Default::default(),
+        }
+    }
+
+    /// Save as ipuz.
+    pub fn to_ipuz(&self) -> Value {
+        let fill = grid_letters(&self.fill);
+        let puzzle: Vec<Vec<Value>> = (0..5)
+            .map(|r| {
+                (0..5)
+                    .map(|c| {
+                        let number = cell_number(r, c).unwrap_or(0);
+                        match fill[r][c] {
+                            Some(ref letter) => json!({ "cell": number, "value": letter }),
+                            None => json!(number),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let clues = |numbers: [usize; 5], texts: &[String; 5]| -> Vec<Value> {
+            numbers.iter().zip(texts).map(|(n, t)| json!([n, t])).collect()
+        };
+        json!({
+            "version": "http://ipuz.org/v2",
+            "kind": ["http://ipuz.org/crossword#1"],
+            "title": self.title,
+            "author": self.author,
+            "dimensions": { "width": 5, "height": 5 },
+            "block": "#",
+            "empty": 0,
+            "puzzle": puzzle,
+            "solution": grid_letters(&self.solution),
+            "clues": {
+                "Across": clues(ACROSS, &self.across),
+                "Down": clues(DOWN, &self.down),
+            },
+        })
+    }
+
+    /// Read ipuz, as saved by [Crossword::to_ipuz()].
+    pub fn from_ipuz(ipuz: &Value) -> Result<Self, Error> {
+        let kinds = ipuz["kind"].as_array().ok_or_else(|| anyhow!("missing kind"))?;
+        if !kinds.iter().any(|k| k.as_str().is_some_and(|k| k.starts_with("http://ipuz.org/crossword"))) {
+            bail!("not an ipuz crossword");
+        }
+        let text = |v: &Value| v.as_str().unwrap_or_default().to_string();
+        let grid = |name: &str, letter: fn(&Value) -> Option<String>| {
+            let rows = ipuz[name].as_array().ok_or_else(|| anyhow!("missing {name}"))?;
+            let letters: Vec<Vec<Option<String>>> = rows
+                .iter()
+                .map(|row| row.as_array().map(|row| row.iter().map(letter).collect()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| anyhow!("{name}: rows must be arrays"))?;
+            grid_from_letters(&letters).map_err(|e| anyhow!("{name}: {e}"))
+        };
+        let solution = grid("solution", |v| v.as_str().map(str::to_string))?;
+        let fill = grid("puzzle", |v| v["value"].as_str().map(str::to_string))?;
+
+        let clues = |direction: &str, numbers: [usize; 5]| -> Result<[String; 5], Error> {
+            let mut texts: [String; 5] = Default::default();
+            for clue in ipuz["clues"][direction].as_array().into_iter().flatten() {
+                let number = clue[0].as_u64().ok_or_else(|| anyhow!("{direction}: bad clue"))?;
+                let Some(i) = numbers.iter().position(|&n| n as u64 == number) else {
+                    bail!("{direction}: no clue {number}");
+                };
+                texts[i] = text(&clue[1]);
+            }
+            Ok(texts)
+        };
+        Ok(Self {
+            title: text(&ipuz["title"]),
+            author: text(&ipuz["author"]),
+            solution,
+            fill,
+            across: clues("Across", ACROSS)?,
+            down: clues("Down", DOWN)?,
+        })
+    }
+
+    /// The clues in `.puz` order: by number, across first.
+    fn puz_clues(&self) -> Vec<&String> {
+        let mut clues: Vec<(usize, usize, &String)> = ACROSS
+            .iter()
+            .zip(&self.across)
+            .map(|(&n, t)| (n, 0, t))
+            .chain(DOWN.iter().zip(&self.down).map(|(&n, t)| (n, 1, t)))
+            .collect();
+        clues.sort_by_key(|&(n, d, _)| (n, d));
+        clues.into_iter().map(|(_, _, t)| t).collect()
+    }
+
+    /// Save as `.puz`.
+    pub fn to_puz(&self) -> Result<Vec<u8>, Error> {
+        let grid_bytes = |grid: &Square, blank: u8| -> Result<Vec<u8>, Error> {
+            grid_letters(grid)
+                .iter()
+                .flatten()
+                .map(|l| l.as_deref().map_or(Ok(blank), puz_byte))
+                .collect()
+        };
+        let solution = grid_bytes(&self.solution, b'.')?;
+        if solution.contains(&b'.') {
+            bail!("the solution has blanks");
+        }
+        let fill = grid_bytes(&self.fill, b'-')?;
+
+        let mut strings = vec![puz_text(&self.title)?, puz_text(&self.author)?, Vec::new()];
+        for clue in self.puz_clues() {
+            strings.push(puz_text(clue)?);
+        }
+        strings.push(Vec::new());
+
+        // Width, height, number of clues, puzzle type and
+        // scrambled state.
+        let mut info = vec![5, 5];
+        info.extend((strings.len() as u16 - 4).to_le_bytes());
+        info.extend(1u16.to_le_bytes());
+        info.extend(0u16.to_le_bytes());
+        let sums = PuzChecksums::new(&info, &solution, &fill, &strings);
+
+        let mut puz = Vec::new();
+        puz.extend(sums.file.to_le_bytes());
+        puz.extend(PUZ_MAGIC);
+        puz.extend(sums.header.to_le_bytes());
+        puz.extend(sums.masked);
+        puz.extend(b"1.3\0");
+        puz.extend([0; 2 + 2 + 12]);
+        puz.extend(&info);
+        puz.extend(&solution);
+        puz.extend(&fill);
+        for s in &strings {
+            puz.extend(s);
+            puz.push(0);
+        }
+        Ok(puz)
+    }
+
+    /// Read `.puz`, checking its checksums.
+    pub fn from_puz(puz: &[u8]) -> Result<Self, Error> {
+        if puz.len() < PUZ_HEADER || &puz[PUZ_MAGIC_AT..PUZ_MAGIC_AT + PUZ_MAGIC.len()] != PUZ_MAGIC {
+            bail!("not a .puz file");
+        }
+        let info = &puz[0x2c..PUZ_HEADER];
+        if info[..2] != [5, 5] {
+            bail!("puzzle is {}×{}, not 5×5", info[0], info[1]);
+        }
+        let nclues = u16::from_le_bytes([info[2], info[3]]) as usize;
+        if nclues != 10 {
+            bail!("puzzle has {nclues} clues, not 10");
+        }
+
+        let cells = 25;
+        let grids = &puz[PUZ_HEADER..];
+        if grids.len() < 2 * cells {
+            bail!("file is cut short");
+        }
+        let (solution, fill) = (&grids[..cells], &grids[cells..2 * cells]);
+        let mut strings: Vec<Vec<u8>> = Vec::new();
+        let mut rest = &grids[2 * cells..];
+        for _ in 0..3 + nclues + 1 {
+            let Some(end) = rest.iter().position(|&b| b == 0) else {
+                bail!("file is cut short");
+            };
+            strings.push(rest[..end].to_vec());
+            rest = &rest[end + 1..];
+        }
+
+        let sums = PuzChecksums::new(info, solution, fill, &strings);
+        let u16_at = |i: usize| u16::from_le_bytes([puz[i], puz[i + 1]]);
+        if u16_at(0x0e) != sums.header {
+            bail!("bad header checksum");
+        }
+        if u16_at(0x00) != sums.file {
+            bail!("bad file checksum");
+        }
+        if puz[0x10..0x18] != sums.masked {
+            bail!("bad masked checksums");
+        }
+
+        let letters = |grid: &[u8], blank: u8| -> Vec<Vec<Option<String>>> {
+            grid.chunks(5)
+                .map(|row| {
+                    row.iter()
+                        .map(|&b| (b != blank).then(|| (b as char).to_string()))
+                        .collect()
+                })
+                .collect()
+        };
+        let text = |s: &[u8]| s.iter().map(|&b| b as char).collect::<String>();
+
+        // Clues are in number order, across first.
+        let mut crossword = Self {
+            title: text(&strings[0]),
+            author: text(&strings[1]),
+            solution: grid_from_letters(&letters(solution, b'.'))?,
+            fill: grid_from_letters(&letters(fill, b'-'))?,
+            across: Default::default(),
+            down: Default::default(),
+        };
+        let mut clues = strings[3..3 + nclues].iter();
+        for n in 1..=9 {
+            if let Some(i) = ACROSS.iter().position(|&a| a == n) {
+                crossword.across[i] = text(clues.next().unwrap());
+            }
+            if let Some(i) = DOWN.iter().position(|&d| d == n) {
+                crossword.down[i] = text(clues.next().unwrap());
+            }
+        }
+        Ok(crossword)
+    }
+}
+
+/// Arguments for the `export` command.
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    #[arg(help="file of squares, or of a puzzle grid, to export")]
+    pub squares: PathBuf,
+    #[arg(short, long, help="output file: .ipuz or .puz")]
+    pub output: PathBuf,
+    #[arg(short, long, default_value_t=0, help="which square in the file to use")]
+    pub index: usize,
+    #[arg(long, help="puzzle grid of the letters to give")]
+    pub givens: Option<PathBuf>,
+    #[arg(long, default_value="Word Square", help="puzzle title")]
+    pub title: String,
+    #[arg(long, default_value="", help="puzzle author")]
+    pub author: String,
+}
+
+impl ExportArgs {
+    /// Run the `export` command: save a square, or the
+    /// puzzle grid with its solution, for crossword tools.
+    /// A square with blanks is a puzzle, and must have
+    /// just one completion.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let squares = read_squares(&self.squares, dicts)?;
+        let Some(square) = squares.get(self.index) else {
+            bail!("{}: no square {}", self.squares.display(), self.index);
+        };
+
+        let (solution, mut fill) = if square.is_full() {
+            (square.clone(), Square::default())
+        } else {
+            let mut state = AppState::solver(args.doubled, 2);
+            let mut completions = Vec::new();
+            state.find_completions(square, dicts, &mut completions);
+            match completions.len() {
+                0 => bail!("puzzle has no solution:\n{}", square.as_string()),
+                1 => (completions.remove(0), square.clone()),
+                _ => bail!("puzzle has more than one solution:\n{}", square.as_string()),
+            }
+        };
+        if let Some(ref path) = self.givens {
+            fill = read_square(path)?;
+            for (r, c) in (0..5).flat_map(|r| (0..5).map(move |c| (r, c))) {
+                if fill.cell(r, c) != 0 && fill.cell(r, c) != solution.cell(r, c) {
+                    bail!("{}: givens do not match the square", path.display());
+                }
+            }
+        }
+
+        // Check that the file reads back as written.
+        let crossword = Crossword::new(&solution, &fill, &self.title, &self.author);
+        let (bytes, read) = match self.output.extension().and_then(|e| e.to_str()) {
+            Some("ipuz") => {
+                let ipuz = crossword.to_ipuz();
+                (ipuz.to_string().into_bytes(), Crossword::from_ipuz(&ipuz)?)
+            }
+            Some("puz") => {
+                let puz = crossword.to_puz()?;
+                let read = Crossword::from_puz(&puz)?;
+                (puz, read)
+            }
+            _ => bail!("{}: output must be .ipuz or .puz", self.output.display()),
+        };
+        if read != crossword {
+            bail!("{}: export does not read back", self.output.display());
+        }
+        std::fs::write(&self.output, bytes)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_crossword() {
+    // A worked checksum.
+    assert_eq!(puz_checksum(b"AB", 0), 0x8062);
+
+    let solution = Square::from_rows(["mores", "uvula", "siren", "enact", "delta"]);
+    let fill = Square::parse("m....\n.....\n..r..\n.....\n....a").unwrap();
+    let mut crossword = Crossword::new(&solution, &fill, "Test", "ws5");
+    crossword.across[2] = "Alarm".to_string();
+    crossword.down[4] = "River mouth".to_string();
+
+    let ipuz = crossword.to_ipuz();
+    assert_eq!(ipuz["puzzle"][0][0], json!({ "cell": 1, "value": "M" }));
+    assert_eq!(ipuz["puzzle"][1][0], 6);
+    assert_eq!(ipuz["puzzle"][1][1], 0);
+    assert_eq!(ipuz["clues"]["Across"][2], json!([7, "Alarm"]));
+    let text = ipuz.to_string();
+    let parsed = serde_json::from_str(&text).unwrap();
+    assert_eq!(Crossword::from_ipuz(&parsed).unwrap(), crossword);
+
+    let puz = crossword.to_puz().unwrap();
+    assert_eq!(&puz[PUZ_HEADER..PUZ_HEADER + 5], b"MORES");
+    assert_eq!(&puz[PUZ_HEADER + 25..PUZ_HEADER + 30], b"M----");
+    assert_eq!(Crossword::from_puz(&puz).unwrap(), crossword);
+
+    // Any change is caught by a checksum: in the header,
+    // the solution, the fill, the title and a clue.
+    assert_eq!(&puz[PUZ_HEADER + 50..PUZ_HEADER + 55], b"Test\0");
+    let clue = puz.windows(5).position(|w| w == b"Alarm").unwrap();
+    for i in [0x30, PUZ_HEADER, PUZ_HEADER + 30, PUZ_HEADER + 50, clue] {
+        let mut bad = puz.clone();
+        bad[i] ^= 1;
+        let e = Crossword::from_puz(&bad).unwrap_err();
+        assert!(e.to_string().contains("checksum"), "byte {i}: {e}");
+    }
+}
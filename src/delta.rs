@@ -0,0 +1,147 @@
+/*!
+Incremental results for a changed dictionary. Rather than
+search again from scratch, the squares found before are
+kept unless they use a removed word, and the search looks
+only for squares that use an added word: each added word is
+pinned in turn into each position with a template. The
+dictionary given is the updated one.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::loader::*;
+use crate::metadata::*;
+use crate::results::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Error;
+
+/// Arguments for the `delta` command.
+#[derive(clap::Args)]
+pub struct DeltaArgs {
+    #[arg(long, help="squares found with the old dictionary")]
+    pub old_results: PathBuf,
+    #[arg(long, help="file of words added to the dictionary")]
+    pub add: Option<PathBuf>,
+    #[arg(long, help="file of words removed from the dictionary")]
+    pub remove: Option<PathBuf>,
+    #[arg(short, long, help="output file, if not the main output file")]
+    pub output: Option<PathBuf>,
+}
+
+/// Read a word list for a delta.
+fn read_words(path: &Option<PathBuf>) -> Result<Vec<Word>, Error> {
+    let Some(path) = path else {
+        return Ok(Vec::new());
+    };
+    let text = std::fs::read_to_string(path)?;
+    let (words, report) = load_words(&text);
+    if !report.rejected.is_empty() {
+        eprintln!("{}: {report}", path.display());
+    }
+    Ok(words)
+}
+
+/// Update `old` squares for a dictionary change: drop the
+/// squares using `removed` words, and add those found by
+/// `state` that use `added` words. The result is sorted.
+/// Returns the new squares and the number of old squares
+/// kept.
+pub fn delta(
+    old: &[Square],
+    added: &[Word],
+    removed: &[Word],
+    dicts: &Dicts,
+    state: &mut AppState,
+) -> (Vec<Square>, usize) {
+    let removed: HashSet<Word> = removed.iter().copied().collect();
+    let dicts = dicts.retain(|_, w| !removed.contains(&w));
+
+    let mut squares: BTreeSet<Square> = old
+        .iter()
+        .filter(|s| (0..10).all(|p| !removed.contains(&s.get_pos(p))))
+        .cloned()
+        .collect();
+    let kept = squares.len();
+
+    let mut results = Vec::new();
+    'words: for &word in added {
+        for p in 0..10 {
+            if !dicts.get(p).is_fit(std::iter::once(word)) {
+                continue;
+            }
+            let mut template = Square::default();
+            template.set_pos(p, word);
+            if !state.find_completions(&template, &dicts, &mut results) {
+                break 'words;
+            }
+        }
+    }
+    squares.extend(results);
+
+    (squares.into_iter().collect(), kept)
+}
+
+impl DeltaArgs {
+    /// Run the `delta` command: update saved squares for a
+    /// changed dictionary.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let old = read_squares(&self.old_results, dicts)?;
+        let added = read_words(&self.add)?;
+        let removed = read_words(&self.remove)?;
+        for &w in &added {
+            if !(0..10).any(|p| dicts.get(p).is_fit(std::iter::once(w))) {
+                eprintln!("ws5: added word {w} is not in the dictionary");
+            }
+        }
+
+        let start = Instant::now();
+        let mut state = AppState::for_search(args)?;
+        let (squares, kept) = delta(&old, &added, &removed, dicts, &mut state);
+
+        let output: &Path = self.output.as_ref().unwrap_or(&args.output);
+        let metadata = Metadata::for_run(args, state.nodes, start.elapsed(), state.stop)?;
+        write_squares(output, args.format, &squares, dicts, state.max_misses > 0, metadata.as_ref())?;
+        summary(output, format!(
+            "kept {kept} of {} squares, {} in all ({} nodes)",
+            old.len(),
+            squares.len(),
+            state.nodes,
+        ));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_delta() {
+    let square = Square::test();
+    let mut words = square.words();
+    let dicts = Dicts::test(&[]);
+    let mut state = AppState::default();
+    let mut old = Vec::new();
+    state.find_completions(&Square::default(), &dicts, &mut old);
+    assert_eq!(old.len(), 1);
+    assert_eq!(old[0], square);
+
+    // Swapping `uvwxy` for `uvwxz` and `ejotz` makes a new
+    // square.
+    let removed = [Word::from_str("uvwxy").unwrap()];
+    let added = [Word::from_str("uvwxz").unwrap(), Word::from_str("ejotz").unwrap()];
+    words.extend(added);
+    let dicts = Dicts::from(Dict::from_words(&words));
+    let mut state = AppState::default();
+    let (squares, kept) = delta(&old, &added, &removed, &dicts, &mut state);
+    assert_eq!(kept, 0);
+    let mut expected = square;
+    expected.set_pos(4, added[0]);
+    assert_eq!(squares, [expected]);
+
+    // No change keeps what was there.
+    let (squares, kept) = delta(&old, &[], &[], &dicts, &mut state);
+    assert_eq!((squares, kept), (old, 1));
+}
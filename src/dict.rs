@@ -5,21 +5,22 @@ using interior mutability for borrow-checker sanity.
 */
 
 use crate::words::*;
+#[cfg(test)]
+use crate::squares::*;
 
 use std::cell::RefCell;
 use std::collections::HashSet;
 
-use anyhow::Error;
 #[cfg(test)]
-use anyhow::bail;
+use anyhow::{Error, bail};
 use caches::{Cache, lfu::WTinyLFUCache as Wtlfu};
 
 /// This contains 5 copies of the dictionary.
 /// The i-th copy contains a split of the dictionary
 /// as sorted by position i. The copy is split
-/// into a list of words that contain the unique letters
-/// 0..25 (`a`..`z`) in order.
-pub type WordIndex = [[Vec<Word>; 26]; 5];
+/// into a list of words for each letter code of the
+/// alphabet (0..25, `a`..`z`, by default) in order.
+pub type WordIndex = [Vec<Vec<Word>>; 5];
 
 /// The dictionary.
 pub struct Dict {
@@ -48,16 +49,6 @@ impl Dict {
         Self { word_list, word_set, word_index, hit_cache, count_cache }
     }
 
-    /// Make a new dictionary from some strings.
-    pub fn new(words: &[&str]) -> Result<Self, Error>  {
-        let mut word_list = words
-            .iter()
-            .map(|w| Word::from_str(w))
-            .collect::<Result<Vec<_>, _>>()?;
-        word_list.sort();
-        Ok(Self::init(word_list))
-    }
-
     /// Make a new dictionary from some words.
     pub fn from_words(words: &[Word]) -> Self {
         let mut word_list = words.to_vec();
@@ -76,39 +67,46 @@ impl Dict {
         let mut hit_cache = self.hit_cache.borrow_mut();
 
         for target in targets {
-            // Do we already know this one?
-            if let Some(&status) = hit_cache.get(&target) {
-                if status {
-                    continue;
-                } else {
-                    return false;
-                }
-            }
-
-            // If the word is complete, just check for it.
-            if target.is_full() {
-                let status = self.word_set.contains(&target);
-                hit_cache.put(target, status);
-                if status {
-                    continue;
-                } else {
-                    return false;
-                }
+            if !self.fit_one(&mut hit_cache, target) {
+                return false;
             }
+        }
 
-            // Dig for matches in the index.
-            let status = self.matches(target).next().is_some();
+        true
+    }
 
-            // Remember what we found.
-            hit_cache.put(target, status);
+    /// Given an iterator over targets, count the ones
+    /// that do not match in the dictionary.
+    pub fn count_misfits<T>(&self, targets: T) -> usize
+    where
+        T: Iterator<Item = Word>
+    {
+        let mut hit_cache = self.hit_cache.borrow_mut();
+        targets
+            .filter(|&target| !self.fit_one(&mut hit_cache, target))
+            .count()
+    }
 
-            // Nope.
-            if !status {
-                return false;
-            }
+    /// Check whether a single target matches in the
+    /// dictionary, using and updating the hit cache.
+    fn fit_one(&self, hit_cache: &mut Wtlfu<Word, bool>, target: Word) -> bool {
+        // Do we already know this one?
+        if let Some(&status) = hit_cache.get(&target) {
+            return status;
         }
 
-        true
+        // If the word is complete, just check for it.
+        // Otherwise dig for matches in the index.
+        let status = if target.is_full() {
+            self.word_set.contains(&target)
+        } else {
+            self.matches(target).next().is_some()
+        };
+
+        // Remember what we found.
+        hit_cache.put(target, status);
+
+        status
     }
 
     /// Return an iterator producing matches of the target from
@@ -163,6 +161,109 @@ impl<'a> IntoIterator for &'a Dict {
     }
 }
 
+/// The dictionaries used for the positions of a square.
+/// Usually the same dictionary serves for every position,
+/// but a true double word square draws its columns from a
+/// separate dictionary, and constraints may restrict the
+/// words allowed in particular positions.
+#[derive(Clone)]
+pub struct Dicts {
+    /// The distinct dictionaries.
+    dicts: Vec<Dict>,
+    /// Index into `dicts` for each position.
+    slots: [usize; 10],
+}
+
+impl Dicts {
+    /// Use separate dictionaries for rows and columns.
+    pub fn split(across: Dict, down: Dict) -> Self {
+        let slots = std::array::from_fn(|p| if p < 5 { 0 } else { 1 });
+        Self { dicts: vec![across, down], slots }
+    }
+
+    /// Get the dictionary for the given position.
+    pub fn get(&self, pos: usize) -> &Dict {
+        &self.dicts[self.slots[pos]]
+    }
+
+    /// The distinct dictionaries.
+    pub fn distinct(&self) -> &[Dict] {
+        &self.dicts
+    }
+
+    /// Index in [Dicts::distinct()] of the dictionary for
+    /// the given position.
+    pub fn index(&self, pos: usize) -> usize {
+        self.slots[pos]
+    }
+
+    /// True if the given positions all share a dictionary.
+    pub fn is_shared(&self, mut posns: impl Iterator<Item = usize>) -> bool {
+        let Some(first) = posns.next() else {
+            return true;
+        };
+        posns.all(|p| self.slots[p] == self.slots[first])
+    }
+
+    /// Restrict the words allowed at the given position to
+    /// those accepted by `filter`.
+    pub fn constrain<F>(&mut self, pos: usize, filter: F)
+    where
+        F: Fn(Word) -> bool
+    {
+        let words: Vec<Word> = self
+            .get(pos)
+            .into_iter()
+            .copied()
+            .filter(|&w| filter(w))
+            .collect();
+        self.slots[pos] = self.dicts.len();
+        self.dicts.push(Dict::from_words(&words));
+    }
+
+    /// Keep only the words of each dictionary that are
+    /// `allowed` at some position using it.
+    pub fn retain<F>(&self, allowed: F) -> Self
+    where
+        F: Fn(usize, Word) -> bool
+    {
+        let dicts = self
+            .dicts
+            .iter()
+            .enumerate()
+            .map(|(i, dict)| {
+                let words: Vec<Word> = dict
+                    .into_iter()
+                    .copied()
+                    .filter(|&w| (0..10).any(|p| self.slots[p] == i && allowed(p, w)))
+                    .collect();
+                Dict::from_words(&words)
+            })
+            .collect();
+        Self { dicts, slots: self.slots }
+    }
+
+    /// Total number of words in the distinct dictionaries.
+    pub fn word_count(&self) -> usize {
+        self.dicts.iter().map(|d| d.into_iter().count()).sum()
+    }
+}
+
+/// A copy has its own empty caches, so that copies can be
+/// used on different threads.
+impl Clone for Dict {
+    fn clone(&self) -> Self {
+        Self::init(self.word_list.clone())
+    }
+}
+
+/// Use the same dictionary for every position.
+impl From<Dict> for Dicts {
+    fn from(dict: Dict) -> Self {
+        Self { dicts: vec![dict], slots: [0; 10] }
+    }
+}
+
 #[cfg(test)]
 impl Dict {
     /// Add a string to the dictionary for tests. Very slow
@@ -182,3 +283,14 @@ impl Dict {
         Ok(())
     }
 }
+
+#[cfg(test)]
+impl Dicts {
+    /// Dictionaries of the words of [Square::test()] and
+    /// the `extra` words.
+    pub fn test(extra: &[&str]) -> Self {
+        let mut words = Square::test().words();
+        words.extend(extra.iter().map(|w| Word::from_str(w).unwrap()));
+        Dicts::from(Dict::from_words(&words))
+    }
+}
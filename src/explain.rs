@@ -0,0 +1,207 @@
+/*!
+Explaining why a partial grid has no completions. The
+simplest reason is a position whose pattern matches no
+dictionary word. Failing that, each given letter in turn
+is dropped if the grid stays unsolvable without it. What is
+left is a minimal set of givens that cannot be completed
+together: these letters alone admit no completion, but
+without any one of them the grid would have one.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::puzzle::*;
+use crate::results::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::path::PathBuf;
+
+use anyhow::Error;
+
+/// Arguments for the `explain` command.
+#[derive(clap::Args)]
+pub struct ExplainArgs {
+    #[arg(help="grid to explain: five lines with `.` for blanks")]
+    pub template: PathBuf,
+}
+
+/// Why a grid can or cannot be completed.
+#[derive(Debug, PartialEq)]
+pub enum Explanation {
+    /// The grid has a completion.
+    Solvable,
+    /// These positions match no dictionary word.
+    NoWords(Vec<(usize, Word)>),
+    /// The grid repeats a word, and words may not be
+    /// doubled.
+    Repeated(Word),
+    /// These givens cannot be completed together. The
+    /// positions cover the givens.
+    Core {
+        grid: Square,
+        positions: Vec<usize>,
+    },
+}
+
+/// Name a list of positions: `rows 2 and 4 with col 3`.
+fn describe(positions: &[usize]) -> String {
+    fn list(kind: &str, indices: &[usize]) -> String {
+        let names: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+        match names.split_last() {
+            None => String::new(),
+            Some((last, [])) => format!("{kind} {last}"),
+            Some((last, rest)) => format!("{kind}s {} and {last}", rest.join(", ")),
+        }
+    }
+
+    let rows: Vec<usize> = positions.iter().filter(|&&p| p < 5).copied().collect();
+    let cols: Vec<usize> = positions.iter().filter(|&&p| p >= 5).map(|p| p - 5).collect();
+    match (rows.is_empty(), cols.is_empty()) {
+        (false, false) => format!("{} with {}", list("row", &rows), list("col", &cols)),
+        (false, true) => list("row", &rows),
+        _ => list("col", &cols),
+    }
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Explanation::Solvable => write!(f, "the grid can be completed"),
+            Explanation::NoWords(misses) => {
+                for (i, (p, target)) in misses.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    if target.is_full() {
+                        write!(f, "{} `{target}` is not a word", pos_name(*p))?;
+                    } else {
+                        write!(f, "{} `{target}` matches no words", pos_name(*p))?;
+                    }
+                }
+                Ok(())
+            }
+            Explanation::Repeated(word) => write!(f, "`{word}` is used twice"),
+            Explanation::Core { grid, positions } => {
+                let patterns: Vec<String> = positions
+                    .iter()
+                    .map(|&p| format!("`{}`", grid.get_pos(p)))
+                    .collect();
+                writeln!(
+                    f,
+                    "{} {} cannot be completed:",
+                    describe(positions),
+                    patterns.join(" "),
+                )?;
+                write!(f, "{}", grid.as_string())
+            }
+        }
+    }
+}
+
+/// Pick positions covering every given of the grid, taking
+/// the position with the most uncovered givens first.
+fn cover(grid: &Square) -> Vec<usize> {
+    let mut uncovered = grid.clone();
+    let mut positions = Vec::new();
+    loop {
+        let count = |p: usize| uncovered.get_pos(p).bits().filter(|&b| b != 0).count();
+        let best = (0..10).max_by_key(|&p| (count(p), std::cmp::Reverse(p)));
+        match best {
+            Some(p) if count(p) > 0 => {
+                uncovered.set_pos(p, Word::default());
+                positions.push(p);
+            }
+            _ => break,
+        }
+    }
+    positions.sort();
+    positions
+}
+
+/// Explain why `template` can or cannot be completed.
+pub fn explain(template: &Square, dicts: &Dicts, doubled: bool) -> Explanation {
+    let misses = misses(template, dicts);
+    if !misses.is_empty() {
+        let misses = misses.into_iter().map(|p| (p, template.get_pos(p))).collect();
+        return Explanation::NoWords(misses);
+    }
+
+    if !doubled && template.has_double() {
+        let full: Vec<Word> = (0..10)
+            .map(|p| template.get_pos(p))
+            .filter(|w| w.is_full())
+            .collect();
+        let repeated = full
+            .iter()
+            .enumerate()
+            .find(|&(i, w)| full[i + 1..].contains(w))
+            .map(|(_, &w)| w)
+            .unwrap_or_default();
+        return Explanation::Repeated(repeated);
+    }
+
+    let solvable = |grid: &Square| count_completions(grid, dicts, doubled, 1) > 0;
+    if solvable(template) {
+        return Explanation::Solvable;
+    }
+
+    // Drop each given that is not needed to block the grid.
+    let mut grid = template.clone();
+    for r in 0..5 {
+        for c in 0..5 {
+            let bits = grid.cell(r, c);
+            if bits == 0 {
+                continue;
+            }
+            grid.set_cell(r, c, 0);
+            if solvable(&grid) {
+                grid.set_cell(r, c, bits);
+            }
+        }
+    }
+    let positions = cover(&grid);
+    Explanation::Core { grid, positions }
+}
+
+impl ExplainArgs {
+    /// Run the `explain` command: say why the template
+    /// cannot be completed.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let template = read_square(&self.template)?;
+        println!("{}", explain(&template, dicts, args.doubled));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_explain() {
+    let dicts = Dicts::test(&[]);
+
+    let mut template = Square::default();
+    template.set_pos(0, Word::from_str("abcde").unwrap());
+    assert_eq!(explain(&template, &dicts, false), Explanation::Solvable);
+
+    template.set_pos(8, Word::from_str("dz...").unwrap());
+    let Explanation::NoWords(misses) = explain(&template, &dicts, false) else {
+        panic!("expected no words");
+    };
+    let row1 = Word::from_str("...z.").unwrap();
+    assert_eq!(misses, [(1, row1), (8, Word::from_str("dz...").unwrap())]);
+
+    // `b` and `l` each fit somewhere, but not together.
+    let mut template = Square::default();
+    template.set_cell(0, 1, Word::from_str("b....").unwrap().get_bits(0));
+    template.set_cell(1, 2, Word::from_str("l....").unwrap().get_bits(0));
+    template.set_cell(4, 4, Word::from_str("y....").unwrap().get_bits(0));
+    let explanation = explain(&template, &dicts, false);
+    let Explanation::Core { ref grid, ref positions } = explanation else {
+        panic!("expected a core");
+    };
+    assert_eq!(grid.cell(4, 4), 0);
+    assert_eq!(positions, &[0, 1]);
+    assert!(explanation.to_string().starts_with("rows 0 and 1 `.b...` `..l..`"));
+
+    assert_eq!(describe(&[2, 4, 8]), "rows 2 and 4 with col 3");
+}
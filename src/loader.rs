@@ -0,0 +1,187 @@
+/*!
+Dictionary file loading. Lines are normalized (trimmed and
+lowercased), comments and blank lines are skipped, and
+duplicates are removed. Lines that cannot be made into a
+[Word] are rejected, and a [LoadReport] records what
+happened to every line for the user's benefit.
+*/
+
+use crate::alphabet::*;
+use crate::words::*;
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// How many example line numbers to show for each kind of
+/// rejection in a report.
+const REPORT_EXAMPLES: usize = 10;
+
+/// Reasons a dictionary line may be rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// Not exactly five letters of the alphabet.
+    Length,
+    /// Contains something other than a letter of the
+    /// alphabet.
+    Char,
+    /// Already seen earlier in the file.
+    Duplicate,
+}
+
+impl Rejection {
+    /// All the rejection kinds, in report order.
+    const ALL: [Rejection; 3] = [
+        Rejection::Length,
+        Rejection::Char,
+        Rejection::Duplicate,
+    ];
+
+    /// Is this rejection fatal when loading strictly?
+    /// Duplicates are harmless, so they are just dropped.
+    pub fn is_error(self) -> bool {
+        !matches!(self, Rejection::Duplicate)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let desc = match self {
+            Rejection::Length => "word length error",
+            Rejection::Char => "invalid char error",
+            Rejection::Duplicate => "duplicate word",
+        };
+        write!(f, "{desc}")
+    }
+}
+
+/// Account of a dictionary load.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    /// Total number of lines read.
+    pub lines: usize,
+    /// Number of comment and blank lines skipped.
+    pub skipped: usize,
+    /// Number of words accepted.
+    pub accepted: usize,
+    /// Rejected lines as (line number, reason, line).
+    pub rejected: Vec<(usize, Rejection, String)>,
+}
+
+impl LoadReport {
+    /// Number of lines rejected for the given reason.
+    pub fn count(&self, reason: Rejection) -> usize {
+        self.rejected.iter().filter(|&&(_, r, _)| r == reason).count()
+    }
+
+    /// True if some line was rejected for a reason that is
+    /// fatal under strict loading.
+    pub fn has_errors(&self) -> bool {
+        self.rejected.iter().any(|&(_, r, _)| r.is_error())
+    }
+
+    /// Describe the errors line by line, in the style of a
+    /// compiler, for strict-mode failure messages.
+    pub fn errors(&self, source: impl fmt::Display) -> String {
+        let mut result = String::new();
+        let errors = self.rejected.iter().filter(|(_, r, _)| r.is_error());
+        for (lineno, reason, line) in errors.take(REPORT_EXAMPLES) {
+            result += &format!("{source}:{lineno}: {reason}: {line:?}\n");
+        }
+        let nerrors = self.rejected.iter().filter(|(_, r, _)| r.is_error()).count();
+        if nerrors > REPORT_EXAMPLES {
+            result += &format!("... and {} more\n", nerrors - REPORT_EXAMPLES);
+        }
+        result
+    }
+}
+
+/// Summary form of the report: counts and example line
+/// numbers for each kind of rejection.
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lines: {} words, {} comments or blank",
+            self.lines, self.accepted, self.skipped,
+        )?;
+        for reason in Rejection::ALL {
+            let n = self.count(reason);
+            if n == 0 {
+                continue;
+            }
+            write!(f, "\n  {reason}: {n} (lines")?;
+            let linenos = self
+                .rejected
+                .iter()
+                .filter(|&&(_, r, _)| r == reason)
+                .map(|&(lineno, _, _)| lineno);
+            for lineno in linenos.take(REPORT_EXAMPLES) {
+                write!(f, " {lineno}")?;
+            }
+            if n > REPORT_EXAMPLES {
+                write!(f, " ...")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Turn a single normalized line into a [Word]. Blanks
+/// (`.`) are not allowed in dictionary words.
+fn parse_line(line: &str) -> Result<Word, Rejection> {
+    let bits = Alphabet::current().bits(line).ok_or(Rejection::Char)?;
+    if bits.contains(&0) {
+        return Err(Rejection::Char);
+    }
+    let bits = bits.try_into().map_err(|_| Rejection::Length)?;
+    Ok(Word::from_bits(bits))
+}
+
+/// Load the words from the text of a dictionary file,
+/// returning them in file order along with a report.
+pub fn load_words(text: &str) -> (Vec<Word>, LoadReport) {
+    let mut report = LoadReport::default();
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        report.lines += 1;
+        let line = line.trim().to_lowercase();
+        if line.is_empty() || line.starts_with('#') {
+            report.skipped += 1;
+            continue;
+        }
+
+        let lineno = i + 1;
+        match parse_line(&line) {
+            Ok(word) if seen.insert(word) => {
+                report.accepted += 1;
+                words.push(word);
+            }
+            Ok(_) => report.rejected.push((lineno, Rejection::Duplicate, line)),
+            Err(reason) => report.rejected.push((lineno, reason, line)),
+        }
+    }
+
+    (words, report)
+}
+
+#[test]
+fn test_load_words() {
+    let text = "# test dictionary\nAback\n  abaft \n\naback\nab\nab'cd\nabase\n";
+    let (words, report) = load_words(text);
+    let words: Vec<String> = words.iter().map(|w| w.as_string()).collect();
+    assert_eq!(words, ["aback", "abaft", "abase"]);
+
+    assert_eq!(report.lines, 8);
+    assert_eq!(report.skipped, 2);
+    assert_eq!(report.accepted, 3);
+    assert_eq!(report.count(Rejection::Duplicate), 1);
+    assert_eq!(report.count(Rejection::Length), 1);
+    assert_eq!(report.count(Rejection::Char), 1);
+    assert!(report.has_errors());
+
+    let linenos: Vec<usize> = report.rejected.iter().map(|&(n, _, _)| n).collect();
+    assert_eq!(linenos, [5, 6, 7]);
+}
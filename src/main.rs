@@ -4,58 +4,209 @@ Find all 5×5 word squares under constraints.
 See the crate README for more information.
 */
 
+mod alphabet;
 mod appstate;
+mod binary;
+mod budget;
+mod build;
+mod constraints;
+mod crossword;
+mod delta;
 mod dict;
+mod explain;
+mod loader;
+mod metadata;
+mod progress;
+mod prune;
+mod puzzle;
+mod rate;
+mod render;
+mod results;
+mod rpc;
 mod words;
 mod search;
+mod serve;
+mod shard;
 mod squares;
+mod stats;
+mod work;
 
+use alphabet::*;
 use appstate::*;
+use constraints::*;
 use squares::*;
+use stats::*;
 use dict::*;
+use loader::*;
+use metadata::*;
+use progress::*;
+use prune::*;
+use results::*;
 
-use std::fs::File;
+use std::path::Path;
 use std::process::exit;
+use std::time::Instant;
 
-use anyhow::Error;
+use anyhow::{Error, anyhow, bail};
 use clap::Parser;
 extern crate serde_json;
 
-/// Split the runner out so that errors can be handled
-/// reasonably in [main()].
-fn run() -> Result<(usize, usize), Error> {
-    let args = Args::parse();
-    let mut app_state = AppState::new(&args);
+/// Load a dictionary from a file, reporting any problems
+/// according to the strictness arguments.
+fn load_dict(path: &Path, args: &Args) -> Result<Dict, Error> {
+    let text = std::fs::read_to_string(path)?;
+    let (words, report) = load_words(&text);
+    let source = path.display();
+    if !args.lenient || args.strict {
+        if report.has_errors() {
+            let errors = report.errors(&source);
+            bail!("{source}: bad dictionary\n{errors}{report}");
+        }
+    } else if !report.rejected.is_empty() {
+        eprintln!("{source}: {report}");
+    }
+    Ok(Dict::from_words(&words))
+}
+
+/// Build the position dictionaries the arguments call for.
+fn load_dicts(args: &Args) -> Result<Dicts, Error> {
+    let dict = load_dict(&args.dict, args)?;
+    let mut dicts = match args.columns {
+        Some(ref path) => Dicts::split(dict, load_dict(path, args)?),
+        None => Dicts::from(dict),
+    };
+    if let Some(ref path) = args.constraints {
+        let text = std::fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let constraints = parse_constraints(&text, dir)
+            .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        dicts.apply(&constraints);
+    }
+    if args.prune {
+        // Pruning drops words a near miss could use.
+        if args.max_misses > 0 {
+            bail!("--prune cannot be used with --max-misses");
+        }
+        let pruned = prune(&dicts);
+        let total = dicts.word_count();
+        eprintln!("pruned {} of {total} words", total - pruned.word_count());
+        dicts = pruned;
+    }
+    Ok(dicts)
+}
+
+/// Run the default command: search for squares.
+fn search(args: &Args, dicts: &Dicts) -> Result<(), Error> {
+    let mut app_state = AppState::for_search(args)?;
+    let format = output_format(&args.output, args.format, args.metadata)?;
+
+    // Restrict the first row to this shard's words.
+    if let Some(shard) = args.shard {
+        app_state.first_rows = Some(shard.range(dicts));
+    }
 
-    // Build the dictionary.
-    let words = std::fs::read_to_string(args.dict)?;
-    let words: Vec<&str> = words
-        .lines()
-        .collect();
-    let dict = Dict::new(words.as_ref())?;
+    // Get the starting grid.
+    let template = match args.template {
+        Some(ref path) => read_square(path)?,
+        None => Square::default(),
+    };
+
+    // Report progress through the first-row words if asked.
+    let count = dicts.get(0).into_iter().count();
+    let words = app_state.first_rows.clone().unwrap_or(0..count);
+    let words = words.start..words.end.min(count);
+    match app_state.trace {
+        TraceStyle::Status => app_state.progress = Some(Progress::new(words)),
+        TraceStyle::Json { .. } => {
+            let dict = args.dict.display().to_string();
+            app_state.progress = Some(Progress::json(words, count, &dict, args.settings()));
+        }
+        _ => (),
+    }
 
     // Run the search.
-    let mut s = Square::default();
+    let start = Instant::now();
     let mut results = Vec::new();
-    app_state.find_all(&mut s, &dict, &mut results);
+    app_state.find_completions(&template, dicts, &mut results);
+    if let Some(ref mut progress) = app_state.progress {
+        progress.finish(app_state.nodes, results.len(), app_state.stop);
+    }
 
-    // Save the result.
-    let save = File::create(args.output)?;
-    serde_json::to_writer(save, &results)?;
+    // Save the result, marking near misses if any.
+    let metadata = Metadata::for_run(args, app_state.nodes, start.elapsed(), app_state.stop)?;
+    let near_misses = app_state.max_misses > 0;
+    write_squares(&args.output, Some(format), &results, dicts, near_misses, metadata.as_ref())?;
+
+    // Save statistics if asked.
+    if let Some(ref path) = args.stats {
+        let mut stats = Stats::default();
+        for s in &results {
+            stats.add(s);
+        }
+        stats.save(path, dicts, STATS_TOP)?;
+    }
 
     // Report success.
-    Ok((results.len(), app_state.nodes))
+    let output = &args.output;
+    summary(output, format!("{} squares ({} nodes)", results.len(), app_state.nodes));
+
+    // Say how far an unfinished search got.
+    if let Some(stop) = app_state.stop {
+        match app_state.first_row {
+            Some(i) => {
+                let row0 = dicts.get(0);
+                let word = row0.into_iter().nth(i).unwrap();
+                let total = row0.into_iter().count();
+                let at = format!("first-row word {} of {total} ({word})", i + 1);
+                summary(output, format!("stopped by {stop} at {at}"));
+            }
+            None => summary(output, format!("stopped by {stop}")),
+        }
+    }
+    Ok(())
+}
+
+/// Split the runner out so that errors can be handled
+/// reasonably in [main()].
+fn run() -> Result<(), Error> {
+    let args = Args::parse();
+
+    // Set up the alphabet.
+    if let Some(ref path) = args.alphabet {
+        let text = std::fs::read_to_string(path)?;
+        let alphabet = Alphabet::parse(&text)
+            .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        alphabet.install()?;
+    }
+
+    // Build the dictionaries.
+    let dicts = load_dicts(&args)?;
+
+    match args.command {
+        None => search(&args, &dicts),
+        Some(Command::Puzzle(ref puzzle_args)) => puzzle_args.run(&args, &dicts),
+        Some(Command::Rate(ref rate_args)) => rate_args.run(&args, &dicts),
+        Some(Command::Build(ref build_args)) => build_args.run(&args, &dicts),
+        Some(Command::Explain(ref explain_args)) => explain_args.run(&args, &dicts),
+        Some(Command::Stats(ref stats_args)) => stats_args.run(&args, &dicts),
+        Some(Command::Prune(ref prune_args)) => prune_args.run(&args, &dicts),
+        Some(Command::Delta(ref delta_args)) => delta_args.run(&args, &dicts),
+        Some(Command::Merge(ref merge_args)) => merge_args.run(&args, &dicts),
+        Some(Command::Serve(ref serve_args)) => serve_args.run(&args, &dicts),
+        Some(Command::Rpc(ref rpc_args)) => rpc_args.run(&args, &dicts),
+        Some(Command::Convert(ref convert_args)) => convert_args.run(&args, &dicts),
+        Some(Command::Render(ref render_args)) => render_args.run(&args, &dicts),
+        Some(Command::Export(ref export_args)) => export_args.run(&args, &dicts),
+        Some(Command::Verify(ref verify_args)) => verify_args.run(&args, &dicts),
+        Some(Command::ServeWork(ref serve_args)) => serve_args.run(&args, &dicts),
+        Some(Command::Worker(ref worker_args)) => worker_args.run(&args, &dicts),
+    }
 }
 
 /// Run the whole operation.
 fn main() {
-    match run() {
-        Err(e) => {
-            eprintln!("ms5: {e}");
-            exit(1);
-        }
-        Ok((nsquares, nnodes)) => {
-            println!("{nsquares} squares ({nnodes} nodes)");
-        }
+    if let Err(e) = run() {
+        eprintln!("ms5: {e}");
+        exit(1);
     }
 }
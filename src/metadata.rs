@@ -0,0 +1,319 @@
+/*!
+Run metadata saved with results, so that a results file
+says what produced it. With `--metadata`, results are saved
+wrapped as
+
+    {"metadata": {...}, "squares": [...]}
+
+with the metadata giving the ws5 version, the dictionary,
+constraints, template and alphabet files used with a hash of
+each, the search settings, the nodes searched, the wall
+time, and whether the search finished.
+
+Results can only be combined with results from the same
+files, by content, and the same settings that decide which
+squares are found. `ws5 verify` and `ws5 merge` check this.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::search::*;
+use crate::shard::*;
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Error, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Settings that change which squares are found. Results
+/// can be combined only if these match. The template is
+/// compared by its hash instead.
+const RESULT_SETTINGS: [&str; 5] = [
+    "doubled",
+    "transposed",
+    "max_misses",
+    "letters",
+    "at_most",
+];
+
+/// Hash of file contents: 64-bit FNV-1a, in hex.
+pub fn file_hash(path: &Path) -> Result<String, Error> {
+    let bytes = std::fs::read(path)?;
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{hash:016x}"))
+}
+
+/// A file the results depend on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+impl FileInfo {
+    /// Describe the file at `path`.
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        Ok(Self { path: path.to_path_buf(), hash: file_hash(path)? })
+    }
+
+    /// Describe the file at `path`, if any.
+    fn maybe(path: &Option<PathBuf>) -> Result<Option<Self>, Error> {
+        path.as_deref().map(Self::new).transpose()
+    }
+}
+
+/// What produced a set of results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The ws5 version.
+    pub version: String,
+    /// The main dictionary.
+    pub dict: FileInfo,
+    /// The column dictionary, if separate.
+    pub columns: Option<FileInfo>,
+    /// The constraints file, if any.
+    pub constraints: Option<FileInfo>,
+    /// The template, if any.
+    #[serde(default)]
+    pub template: Option<FileInfo>,
+    /// The alphabet file, if not the default.
+    #[serde(default)]
+    pub alphabet: Option<FileInfo>,
+    /// The search settings, as from [Args::settings()].
+    pub settings: Value,
+    /// Nodes searched.
+    pub nodes: usize,
+    /// Wall time, in seconds.
+    pub elapsed: f64,
+    /// True if the search was done.
+    pub finished: bool,
+    /// Why the search stopped early, if it did, as from
+    /// [Stop::key()].
+    pub stop: Option<String>,
+}
+
+impl Metadata {
+    /// Describe a run with the given arguments.
+    pub fn new(
+        args: &Args,
+        nodes: usize,
+        elapsed: Duration,
+        stop: Option<Stop>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            dict: FileInfo::new(&args.dict)?,
+            columns: FileInfo::maybe(&args.columns)?,
+            constraints: FileInfo::maybe(&args.constraints)?,
+            template: FileInfo::maybe(&args.template)?,
+            alphabet: FileInfo::maybe(&args.alphabet)?,
+            settings: args.settings(),
+            nodes,
+            elapsed: elapsed.as_secs_f64(),
+            finished: stop.is_none(),
+            stop: stop.map(|s| s.key().to_string()),
+        })
+    }
+
+    /// Describe a run if asked to with `--metadata`.
+    pub fn for_run(
+        args: &Args,
+        nodes: usize,
+        elapsed: Duration,
+        stop: Option<Stop>,
+    ) -> Result<Option<Self>, Error> {
+        if !args.metadata {
+            return Ok(None);
+        }
+        Self::new(args, nodes, elapsed, stop).map(Some)
+    }
+
+    /// Fail unless the squares of results with this
+    /// metadata and `other` are made of the same words: the
+    /// dictionaries, constraints and alphabet match.
+    pub fn check_words(&self, other: &Metadata) -> Result<(), Error> {
+        if self.dict.hash != other.dict.hash {
+            bail!("different dictionary");
+        }
+        let hash = |f: &Option<FileInfo>| f.as_ref().map(|f| f.hash.clone());
+        if hash(&self.columns) != hash(&other.columns) {
+            bail!("different column dictionary");
+        }
+        if hash(&self.constraints) != hash(&other.constraints) {
+            bail!("different constraints");
+        }
+        if hash(&self.alphabet) != hash(&other.alphabet) {
+            bail!("different alphabet");
+        }
+        Ok(())
+    }
+
+    /// Fail unless results with this metadata and `other`
+    /// can be combined.
+    pub fn check(&self, other: &Metadata) -> Result<(), Error> {
+        self.check_words(other)?;
+        let hash = |f: &Option<FileInfo>| f.as_ref().map(|f| f.hash.clone());
+        if hash(&self.template) != hash(&other.template) {
+            bail!("different template");
+        }
+        for name in RESULT_SETTINGS {
+            let (mine, theirs) = (&self.settings[name], &other.settings[name]);
+            if mine != theirs {
+                bail!("different {name} setting: {mine} and {theirs}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Combine the metadata of results being merged, which
+    /// should have been checked. The merge is finished if
+    /// every part is, and the parts are either whole
+    /// searches or every shard of one.
+    pub fn merge(parts: &[Metadata]) -> Metadata {
+        let mut merged = parts[0].clone();
+        merged.version = env!("CARGO_PKG_VERSION").to_string();
+        merged.nodes = parts.iter().map(|m| m.nodes).sum();
+        merged.elapsed = parts.iter().map(|m| m.elapsed).sum();
+        merged.stop = parts.iter().find_map(|m| m.stop.clone());
+
+        let shards: Vec<Option<Shard>> = parts
+            .iter()
+            .map(|m| m.settings["shard"].as_str().and_then(|s| s.parse().ok()))
+            .collect();
+        let whole = if shards.iter().all(|s| s.is_none()) {
+            true
+        } else if let Some(Some(first)) = shards.first() {
+            let indices: BTreeSet<usize> = shards
+                .iter()
+                .filter_map(|s| s.filter(|s| s.count == first.count))
+                .map(|s| s.index)
+                .collect();
+            indices.len() == first.count && shards.iter().all(|s| s.is_some())
+        } else {
+            false
+        };
+        merged.finished = whole && parts.iter().all(|m| m.finished);
+        if whole {
+            merged.settings["shard"] = Value::Null;
+        }
+        merged
+    }
+}
+
+/// Arguments for the `verify` command.
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    #[arg(required=true, help="saved squares to check")]
+    pub squares: Vec<PathBuf>,
+}
+
+impl VerifyArgs {
+    /// Run the `verify` command: check that saved squares
+    /// are squares under the dictionaries given, and that
+    /// their metadata, if any, matches the dictionaries,
+    /// constraints and alphabet given and each other.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let given = Metadata::new(args, 0, Duration::ZERO, None)?;
+        let mut problems = 0;
+        let mut first: Option<(&Path, Metadata)> = None;
+        for path in &self.squares {
+            let source = path.display();
+            let results = read_results(path, dicts)?;
+
+            if let Some(metadata) = results.metadata {
+                if let Err(e) = given.check_words(&metadata) {
+                    eprintln!("{source}: does not match the files given: {e}");
+                    problems += 1;
+                }
+                match first {
+                    Some((first_path, ref first)) => {
+                        if let Err(e) = first.check(&metadata) {
+                            eprintln!("{source}: does not match {}: {e}", first_path.display());
+                            problems += 1;
+                        }
+                    }
+                    None => first = Some((path, metadata)),
+                }
+            } else {
+                eprintln!("{source}: no metadata");
+            }
+
+            let mut bad = 0;
+            for (i, s) in results.squares.iter().enumerate() {
+                let misses = misses(s, dicts);
+                if !s.is_full() || misses.len() > args.max_misses {
+                    eprintln!("{source}: square {i}: not a square\n{}", s.as_string());
+                    bad += 1;
+                } else if !args.doubled && s.has_double() {
+                    eprintln!("{source}: square {i}: repeats a word\n{}", s.as_string());
+                    bad += 1;
+                }
+            }
+            println!("{source}: {} squares, {bad} bad", results.squares.len());
+            problems += bad;
+        }
+        if problems > 0 {
+            bail!("{problems} problem(s) found");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_metadata() {
+    use serde_json::json;
+
+    let path = Path::new("small-dict.txt");
+    let dict = FileInfo::new(path).unwrap();
+    assert_eq!(dict.hash.len(), 16);
+    assert_eq!(file_hash(path).unwrap(), dict.hash);
+
+    let shard = |shard: &str, finished: bool| Metadata {
+        version: "0".to_string(),
+        dict: dict.clone(),
+        columns: None,
+        constraints: None,
+        template: None,
+        alphabet: None,
+        settings: json!({ "doubled": false, "shard": shard, "limit": null }),
+        nodes: 10,
+        elapsed: 1.0,
+        finished,
+        stop: None,
+    };
+    let parts = [shard("1/2", true), shard("2/2", true)];
+    parts[0].check(&parts[1]).unwrap();
+    let merged = Metadata::merge(&parts);
+    assert_eq!(merged.nodes, 20);
+    assert!(merged.finished);
+    assert_eq!(merged.settings["shard"], Value::Null);
+
+    // Half the shards are not the whole search.
+    assert!(!Metadata::merge(&parts[..1]).finished);
+    assert!(!Metadata::merge(&[shard("1/2", true), shard("2/2", false)]).finished);
+
+    let mut doubled = shard("1/2", true);
+    doubled.settings["doubled"] = json!(true);
+    assert!(parts[0].check(&doubled).is_err());
+    let mut other = shard("1/2", true);
+    other.dict.hash = "0".repeat(16);
+    assert!(parts[0].check(&other).is_err());
+
+    // The template is compared by content, not path.
+    let template = |path: &str, hash: &str| {
+        let template = Some(FileInfo { path: PathBuf::from(path), hash: hash.to_string() });
+        Metadata { template, ..shard("1/2", true) }
+    };
+    template("a.txt", "1").check(&template("b.txt", "1")).unwrap();
+    assert!(template("a.txt", "1").check(&template("a.txt", "2")).is_err());
+    let alphabet = Some(FileInfo { path: PathBuf::from("greek.txt"), hash: "1".to_string() });
+    let greek = Metadata { alphabet, ..shard("1/2", true) };
+    assert!(parts[0].check(&greek).is_err());
+    assert!(parts[0].check_words(&greek).is_err());
+}
@@ -0,0 +1,294 @@
+/*!
+Progress reports for long searches: which first-row word
+the search is under, out of how many, the search rate, the
+squares found so far, and an estimated time to finish. The
+estimate takes the mean size of the first-row subtrees
+finished so far as the size of each one left.
+
+On a terminal the report is redrawn in place a few times a
+second. Otherwise a line is logged now and then.
+
+With `--progress json` the reports are instead JSON events,
+one object per line on standard error: `start`, `status`
+every second, `solution` for each square with `--progress
+json-solutions`, and `finish`. The README lists their
+fields, which are meant to stay stable: fields may be
+added, but not removed or changed.
+*/
+
+use crate::appstate::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::io::IsTerminal;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+/// How often to report on a terminal and in a log.
+const TTY_INTERVAL: Duration = Duration::from_millis(250);
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+const JSON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Progress through a search.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// Report with JSON events.
+    json: bool,
+    /// Redraw in place rather than log lines.
+    tty: bool,
+    /// Time between reports.
+    interval: Duration,
+    /// When the search started.
+    start: Instant,
+    /// When the last report was made.
+    last: Instant,
+    /// First-row words to be searched, as indices into the
+    /// row 0 dictionary.
+    words: Range<usize>,
+    /// First-row subtrees finished, and their total nodes.
+    done_words: usize,
+    done_nodes: usize,
+    /// Node count when the current first-row subtree
+    /// started, if one has.
+    word_start: Option<usize>,
+    /// A line is drawn and not yet ended.
+    drawn: bool,
+}
+
+/// Format a duration roughly, as `1h02m`, `3m05s` or `12s`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}h{m:02}m")
+    } else if m > 0 {
+        format!("{m}m{s:02}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
+/// Write an event to standard error as one line.
+fn send(event: Value) {
+    eprintln!("{event}");
+}
+
+impl Progress {
+    /// Start reporting on standard error, on a search of
+    /// the first-row words in range `words`.
+    pub fn new(words: Range<usize>) -> Self {
+        let tty = std::io::stderr().is_terminal();
+        let now = Instant::now();
+        Self {
+            json: false,
+            tty,
+            interval: if tty { TTY_INTERVAL } else { LOG_INTERVAL },
+            start: now,
+            last: now,
+            words,
+            done_words: 0,
+            done_nodes: 0,
+            word_start: None,
+            drawn: false,
+        }
+    }
+
+    /// Start reporting JSON events on standard error, and
+    /// send the `start` event for a dictionary of `total`
+    /// first-row words with the given `dict` path and
+    /// `flags`.
+    pub fn json(words: Range<usize>, total: usize, dict: &str, flags: Value) -> Self {
+        let progress = Self {
+            json: true,
+            tty: false,
+            interval: JSON_INTERVAL,
+            ..Self::new(words)
+        };
+        send(progress.start_json(total, dict, flags));
+        progress
+    }
+
+    /// The `start` event. `words` is the size of the whole
+    /// first-row dictionary, and `range` the part of it to
+    /// be searched, which is smaller under `--shard`.
+    pub fn start_json(&self, total: usize, dict: &str, flags: Value) -> Value {
+        json!({
+            "event": "start",
+            "words": total,
+            "range": [self.words.start, self.words.end],
+            "dict": dict,
+            "flags": flags,
+        })
+    }
+
+    /// Note that the search has moved on to a new
+    /// first-row word after `nodes` nodes.
+    pub fn next_word(&mut self, nodes: usize) {
+        if let Some(start) = self.word_start {
+            self.done_words += 1;
+            self.done_nodes += nodes - start;
+        }
+        self.word_start = Some(nodes);
+    }
+
+    /// Estimated nodes left to search, with the search
+    /// under first-row word `index` at `nodes` nodes.
+    fn nodes_left(&self, index: usize, nodes: usize) -> Option<usize> {
+        if self.done_words == 0 {
+            return None;
+        }
+        let mean = self.done_nodes / self.done_words;
+        let words_left = self.words.end.saturating_sub(index);
+        let current = nodes - self.word_start.unwrap_or(nodes);
+        Some((mean * words_left).saturating_sub(current))
+    }
+
+    /// Nodes per second so far.
+    fn rate(&self, nodes: usize) -> f64 {
+        nodes as f64 / self.start.elapsed().as_secs_f64().max(0.001)
+    }
+
+    /// Estimated time left.
+    fn eta(&self, first_row: Option<usize>, nodes: usize) -> Option<Duration> {
+        let left = self.nodes_left(first_row?, nodes)?;
+        Some(Duration::from_secs_f64(left as f64 / self.rate(nodes)))
+    }
+
+    /// The report text.
+    pub fn status(&self, first_row: Option<usize>, nodes: usize, squares: usize) -> String {
+        let mut status = String::new();
+        if let Some(i) = first_row {
+            let n = self.words.len();
+            status += &format!("word {}/{n}  ", i + 1 - self.words.start);
+        }
+        status += &format!("{:.0} nodes/s  {squares} squares  ", self.rate(nodes));
+        let eta = self.eta(first_row, nodes).map(format_duration);
+        status += &format!("ETA {}", eta.as_deref().unwrap_or("?"));
+        status
+    }
+
+    /// The report as a `status` event, with `prefix` the
+    /// first row so far.
+    pub fn status_json(
+        &self,
+        first_row: Option<usize>,
+        prefix: Word,
+        nodes: usize,
+        squares: usize,
+    ) -> Value {
+        json!({
+            "event": "status",
+            "elapsed": self.start.elapsed().as_secs_f64(),
+            "nodes": nodes,
+            "squares": squares,
+            "rate": self.rate(nodes),
+            "first_row": first_row.map(|i| i - self.words.start),
+            "prefix": (!prefix.is_empty()).then(|| prefix.as_string()),
+            "eta": self.eta(first_row, nodes).map(|d| d.as_secs_f64()),
+        })
+    }
+
+    /// Report if it is time to.
+    pub fn update(
+        &mut self,
+        first_row: Option<usize>,
+        prefix: Word,
+        nodes: usize,
+        squares: usize,
+    ) {
+        if self.last.elapsed() < self.interval {
+            return;
+        }
+        self.last = Instant::now();
+        if self.json {
+            send(self.status_json(first_row, prefix, nodes, squares));
+            return;
+        }
+        let status = self.status(first_row, nodes, squares);
+        if self.tty {
+            eprint!("\r\x1b[K{status}");
+            self.drawn = true;
+        } else {
+            eprintln!("{status}");
+        }
+    }
+
+    /// Report a solution, if reporting them.
+    pub fn solution(&self, square: &Square, nodes: usize, squares: usize) {
+        if self.json {
+            send(json!({
+                "event": "solution",
+                "square": square,
+                "nodes": nodes,
+                "squares": squares,
+            }));
+        }
+    }
+
+    /// End the report, saying why the search stopped if it
+    /// did not finish.
+    pub fn finish(&mut self, nodes: usize, squares: usize, stop: Option<Stop>) {
+        if self.json {
+            send(json!({
+                "event": "finish",
+                "elapsed": self.start.elapsed().as_secs_f64(),
+                "nodes": nodes,
+                "squares": squares,
+                "finished": stop.is_none(),
+                "stop": stop.map(|s| s.key()),
+            }));
+            return;
+        }
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            self.drawn = false;
+        }
+        let elapsed = self.start.elapsed();
+        eprintln!(
+            "searched {nodes} nodes in {}, {squares} squares",
+            format_duration(elapsed),
+        );
+    }
+}
+
+#[test]
+fn test_progress() {
+    assert_eq!(format_duration(Duration::from_secs(12)), "12s");
+    assert_eq!(format_duration(Duration::from_secs(185)), "3m05s");
+    assert_eq!(format_duration(Duration::from_secs(3720)), "1h02m");
+
+    // Words 10 and 11 took 100 and 300 nodes: with 50 nodes
+    // into word 12 of 20, the 8 left should take 1550 more.
+    let mut progress = Progress::new(10..20);
+    assert_eq!(progress.nodes_left(10, 0), None);
+    progress.next_word(0);
+    progress.next_word(100);
+    progress.next_word(400);
+    assert_eq!(progress.nodes_left(12, 450), Some(1550));
+    assert!(progress.status(Some(12), 450, 3).starts_with("word 3/10  "));
+}
+
+#[test]
+fn test_progress_json() {
+    let prefix = Word::from_str("abase").unwrap();
+    let mut progress = Progress { json: true, ..Progress::new(1..3) };
+    progress.next_word(0);
+    progress.next_word(100);
+    let status = progress.status_json(Some(2), prefix, 150, 4);
+    assert_eq!(status["event"], "status");
+    assert_eq!(status["first_row"], 1);
+    assert_eq!(status["prefix"], "abase");
+    assert_eq!(status["nodes"], 150);
+    assert_eq!(status["squares"], 4);
+    assert!(status["eta"].is_number());
+
+    let start = progress.start_json(5, "dict.txt", json!({}));
+    assert_eq!(start["words"], 5);
+    assert_eq!(start["range"], json!([1, 3]));
+
+    let status = Progress::new(0..3).status_json(None, Word::default(), 0, 0);
+    assert_eq!(status["prefix"], Value::Null);
+    assert_eq!(status["eta"], Value::Null);
+}
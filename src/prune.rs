@@ -0,0 +1,160 @@
+/*!
+Dictionary pruning. Many dictionary words can never appear
+in any square: for example, a row word with a letter that
+no column word has at that place. Pruning finds and drops
+them before the search starts, by generalized arc
+consistency over the ten positions of the square.
+
+Each position keeps a domain of the words still possible
+there, and a count of the live words in each [WordIndex]
+bucket: the words with a given letter at a given place. A
+word in a row is supported when, for each of its letters,
+the crossing column has live words with that letter where
+the row crosses it, and likewise for columns. Unsupported
+words are dropped and the counts updated, over and over,
+until nothing changes. What is left is the words possible
+at some position.
+
+Pruning assumes every position is a word, so it cannot be
+used with near misses.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::words::*;
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{Error, bail};
+
+/// Arguments for the `prune` command.
+#[derive(clap::Args)]
+pub struct PruneArgs {
+    #[arg(short, long, help="save the reduced dictionary to this file")]
+    pub output: Option<PathBuf>,
+}
+
+/// The live words at one position, with their bucket
+/// counts.
+struct Domain {
+    words: Vec<Word>,
+    live: Vec<bool>,
+    counts: [Vec<usize>; 5],
+}
+
+impl Domain {
+    fn new(dict: &Dict) -> Self {
+        let words: Vec<Word> = dict.into_iter().copied().collect();
+        let live = vec![true; words.len()];
+        let index = Word::build_word_index(&words);
+        let counts = index.map(|buckets| buckets.iter().map(|b| b.len()).collect());
+        Self { words, live, counts }
+    }
+
+    /// True if some live word has letter `bits` at place
+    /// `i`.
+    fn has(&self, i: usize, bits: u8) -> bool {
+        self.counts[i][(bits & 0x1f) as usize] > 0
+    }
+}
+
+/// The position crossing position `pos` at its place `i`,
+/// and the place in the crossing word where they meet.
+fn crossing(pos: usize, i: usize) -> (usize, usize) {
+    if pos < 5 {
+        (5 + i, pos)
+    } else {
+        (i, pos - 5)
+    }
+}
+
+/// Prune the dictionaries to the words that can appear at
+/// some position. Returns the pruned dictionaries.
+pub fn prune(dicts: &Dicts) -> Dicts {
+    let mut domains: Vec<Domain> = (0..10).map(|p| Domain::new(dicts.get(p))).collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for p in 0..10 {
+            for k in 0..domains[p].words.len() {
+                if !domains[p].live[k] {
+                    continue;
+                }
+                let word = domains[p].words[k];
+                let supported = word.bits().enumerate().all(|(i, bits)| {
+                    let (q, j) = crossing(p, i);
+                    domains[q].has(j, bits)
+                });
+                if !supported {
+                    let domain = &mut domains[p];
+                    domain.live[k] = false;
+                    for (i, bits) in word.bits().enumerate() {
+                        domain.counts[i][(bits & 0x1f) as usize] -= 1;
+                    }
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let allowed: Vec<HashSet<Word>> = domains
+        .iter()
+        .map(|d| {
+            d.words
+                .iter()
+                .zip(&d.live)
+                .filter(|&(_, &live)| live)
+                .map(|(&w, _)| w)
+                .collect()
+        })
+        .collect();
+    dicts.retain(|p, w| allowed[p].contains(&w))
+}
+
+impl PruneArgs {
+    /// Run the `prune` command: report how much pruning
+    /// removes, and save the reduced dictionary.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        // One word list cannot keep the separate pruning of
+        // row and column words.
+        if self.output.is_some() && args.columns.is_some() {
+            bail!("prune --output cannot be used with --columns");
+        }
+
+        // With --prune the dictionaries are already pruned,
+        // and the pruning reported.
+        let pruned = if args.prune {
+            None
+        } else {
+            let pruned = prune(dicts);
+            let total = dicts.word_count();
+            println!("pruned {} of {total} words", total - pruned.word_count());
+            Some(pruned)
+        };
+        let pruned = pruned.as_ref().unwrap_or(dicts);
+
+        if let Some(ref path) = self.output {
+            let words: BTreeSet<Word> = (0..10).flat_map(|p| pruned.get(p)).copied().collect();
+            let text: String = words.iter().map(|w| w.as_string() + "\n").collect();
+            std::fs::write(path, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_prune() {
+    use crate::squares::*;
+
+    // No word crosses the `z` of `abcdz`, wherever it is.
+    let dicts = Dicts::test(&["abcdz"]);
+
+    let pruned = prune(&dicts);
+    assert_eq!(pruned.word_count(), 10);
+    assert!(pruned.is_shared(0..10));
+    let mut results = Vec::new();
+    AppState::solver(false, 10).find_completions(&Square::default(), &pruned, &mut results);
+    assert_eq!(results.len(), 2);
+}
@@ -0,0 +1,183 @@
+/*!
+Puzzle generation. A puzzle is a partly filled square whose
+blanks can be filled in only one way from the dictionary.
+Starting from a complete square, given letters are removed
+one at a time (or in symmetric pairs) for as long as the
+puzzle stays uniquely solvable. What is left is a minimal
+set of givens: removing any more would allow a second
+solution.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::squares::*;
+
+use std::path::PathBuf;
+
+use anyhow::{Error, bail};
+
+/// Arguments for the `puzzle` command.
+#[derive(clap::Args)]
+pub struct PuzzleArgs {
+    #[arg(help="file of squares to make a puzzle from")]
+    pub squares: PathBuf,
+    #[arg(short, long, default_value_t=0, help="which square in the file to use")]
+    pub index: usize,
+    #[arg(short, long, help="stop removing letters at this many givens")]
+    pub givens: Option<usize>,
+    #[arg(short, long, help="keep the givens symmetric under a half turn")]
+    pub symmetric: bool,
+    #[arg(long, default_value_t=0, help="seed for the order letters are removed in")]
+    pub seed: u64,
+    #[arg(short, long, help="also save the puzzle grid to this file")]
+    pub output: Option<PathBuf>,
+}
+
+/// Count the completions of a partial grid, stopping once
+/// `limit` are found. A transposed completion is a
+/// different completion here.
+pub fn count_completions(
+    grid: &Square,
+    dicts: &Dicts,
+    doubled: bool,
+    limit: usize,
+) -> usize {
+    let mut state = AppState::solver(doubled, limit);
+    let mut results = Vec::new();
+    state.find_completions(grid, dicts, &mut results);
+    results.len()
+}
+
+/// Number of given (non-blank) cells in a grid.
+pub fn count_givens(grid: &Square) -> usize {
+    (0..5).flat_map(|r| (0..5).map(move |c| (r, c)))
+        .filter(|&(r, c)| grid.cell(r, c) != 0)
+        .count()
+}
+
+/// Shuffle deterministically, using a xorshift generator
+/// started from the seed.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed ^ 0x9e37_79b9_7f4a_7c15;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Make a puzzle with the given solution, which must be a
+/// word square in the dictionary. Letters are removed in
+/// an order set by `seed`, stopping early if the number of
+/// givens reaches `goal`. With `symmetric`, letters are
+/// removed in pairs related by a half turn of the grid.
+pub fn make_puzzle(
+    solution: &Square,
+    dicts: &Dicts,
+    doubled: bool,
+    goal: Option<usize>,
+    symmetric: bool,
+    seed: u64,
+) -> Square {
+    // Group the cells that must be removed together.
+    let mut groups: Vec<Vec<(usize, usize)>> = Vec::new();
+    for r in 0..5 {
+        for c in 0..5 {
+            let mirror = (4 - r, 4 - c);
+            if !symmetric {
+                groups.push(vec![(r, c)]);
+            } else if (r, c) < mirror {
+                groups.push(vec![(r, c), mirror]);
+            } else if (r, c) == mirror {
+                groups.push(vec![(r, c)]);
+            }
+        }
+    }
+    shuffle(&mut groups, seed);
+
+    let mut puzzle = solution.clone();
+    let mut ngivens = 25;
+    for group in groups {
+        if goal.is_some_and(|g| ngivens <= g) {
+            break;
+        }
+
+        for &(r, c) in &group {
+            puzzle.set_cell(r, c, 0);
+        }
+        if count_completions(&puzzle, dicts, doubled, 2) == 1 {
+            ngivens -= group.len();
+        } else {
+            for &(r, c) in &group {
+                puzzle.set_cell(r, c, solution.cell(r, c));
+            }
+        }
+    }
+
+    puzzle
+}
+
+impl PuzzleArgs {
+    /// Run the `puzzle` command: make a puzzle from the
+    /// chosen square and show it with its solution.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let squares = read_squares(&self.squares, dicts)?;
+        let Some(solution) = squares.get(self.index) else {
+            bail!("{}: no square {}", self.squares.display(), self.index);
+        };
+        if count_completions(solution, dicts, args.doubled, 1) != 1 {
+            bail!("not a word square in this dictionary:\n{}", solution.as_string());
+        }
+
+        let puzzle = make_puzzle(
+            solution,
+            dicts,
+            args.doubled,
+            self.givens,
+            self.symmetric,
+            self.seed,
+        );
+        let ngivens = count_givens(&puzzle);
+        if let Some(goal) = self.givens && ngivens > goal {
+            eprintln!("ws5: could not get below {ngivens} givens");
+        }
+
+        println!("puzzle ({ngivens} givens):\n{}\n", puzzle.as_string());
+        println!("solution:\n{}", solution.as_string());
+        if let Some(ref path) = self.output {
+            std::fs::write(path, puzzle.as_string() + "\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_make_puzzle() {
+    let solution = Square::test();
+    let dicts = Dicts::test(&["abcdz", "ezjoy"]);
+
+    for symmetric in [false, true] {
+        let puzzle = make_puzzle(&solution, &dicts, false, None, symmetric, 1);
+        assert_eq!(count_completions(&puzzle, &dicts, false, 2), 1);
+
+        // The givens agree with the solution, and none can
+        // be removed without losing uniqueness.
+        for r in 0..5 {
+            for c in 0..5 {
+                let bits = puzzle.cell(r, c);
+                if bits == 0 || symmetric {
+                    continue;
+                }
+                assert_eq!(bits, solution.cell(r, c));
+                let mut fewer = puzzle.clone();
+                fewer.set_cell(r, c, 0);
+                assert!(count_completions(&fewer, &dicts, false, 2) > 1);
+            }
+        }
+    }
+
+    let puzzle = make_puzzle(&solution, &dicts, false, Some(20), false, 1);
+    assert_eq!(count_givens(&puzzle), 20);
+}
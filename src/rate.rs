@@ -0,0 +1,372 @@
+/*!
+Puzzle difficulty rating. A puzzle's difficulty is judged
+from how the solver and a simulated human behave on it:
+
+* How many nodes the solver searches to solve the puzzle
+  and prove the solution unique.
+* How many candidates the solver must consider at each
+  most-constrained step along the way to the solution.
+* Whether a human could solve it one position at a time,
+  always filling a position that has only one candidate
+  word left, or would have to guess.
+* How rare the words to be found are.
+
+Each of these is scaled to a 0..1 score, and the weighted
+scores are summed into a difficulty from 0 to 100.
+*/
+
+use crate::alphabet::*;
+use crate::appstate::*;
+use crate::dict::*;
+use crate::loader::*;
+use crate::results::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Error, anyhow, bail};
+
+/// Weights of the scores in the difficulty.
+const NODES_WEIGHT: f64 = 0.25;
+const BRANCHING_WEIGHT: f64 = 0.2;
+const GUESS_WEIGHT: f64 = 0.35;
+const RARITY_WEIGHT: f64 = 0.2;
+
+/// Arguments for the `rate` command.
+#[derive(clap::Args)]
+pub struct RateArgs {
+    #[arg(required=true, help="puzzle grid files: five lines with `.` for blanks")]
+    pub puzzles: Vec<PathBuf>,
+    #[arg(long, help="word list ordered from most to least common, for rarity")]
+    pub ranks: Option<PathBuf>,
+}
+
+/// How rare words are, from 0 (most common) to 1.
+pub enum Rarity {
+    /// Position in a list ordered most common first.
+    Ranked(HashMap<Word, f64>),
+    /// Lacking word frequencies, judge by how usual the
+    /// letters at each position of the word are in the
+    /// dictionary.
+    Letters {
+        /// A table for each distinct dictionary.
+        tables: Vec<LetterTable>,
+        /// Index into `tables` for each position.
+        slots: [usize; 10],
+    },
+}
+
+/// How usual the letters of a dictionary's words are. Words
+/// are scored by the log frequency of their letters in
+/// position.
+pub struct LetterTable {
+    /// Count of each letter code at each position.
+    counts: [[usize; MAX_LETTERS]; 5],
+    /// Scores of the dictionary words, lowest first.
+    scores: Vec<f64>,
+}
+
+impl LetterTable {
+    /// Count the letters of `dict` and score its words.
+    pub fn new(dict: &Dict) -> Self {
+        let mut counts = [[0usize; MAX_LETTERS]; 5];
+        for w in dict {
+            for (i, b) in w.bits().enumerate() {
+                counts[i][(b & 0x1f) as usize] += 1;
+            }
+        }
+        let mut table = LetterTable { counts, scores: Vec::new() };
+        let mut scores: Vec<f64> = dict.into_iter().map(|&w| table.score(w)).collect();
+        scores.sort_by(f64::total_cmp);
+        table.scores = scores;
+        table
+    }
+
+    fn score(&self, w: Word) -> f64 {
+        w.bits()
+            .enumerate()
+            .map(|(i, b)| (self.counts[i][(b & 0x1f) as usize] as f64).ln())
+            .sum()
+    }
+
+    /// The fraction of dictionary words that score higher
+    /// than `word`.
+    pub fn rarity(&self, word: Word) -> f64 {
+        let target = self.score(word);
+        let above = self.scores.len() - self.scores.partition_point(|&s| s <= target);
+        above as f64 / self.scores.len().max(1) as f64
+    }
+}
+
+impl Rarity {
+    /// Rarity from a word list ordered most common first.
+    /// Words not in the list are rarest.
+    pub fn ranked(words: &[Word]) -> Self {
+        let n = words.len().max(1) as f64;
+        let ranks = words
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| (w, i as f64 / n))
+            .collect();
+        Rarity::Ranked(ranks)
+    }
+
+    /// Rarity by the letters of the words of `dicts`. Each
+    /// distinct dictionary is counted once, however many
+    /// positions and puzzles share it.
+    pub fn letters(dicts: &Dicts) -> Self {
+        let tables = dicts.distinct().iter().map(LetterTable::new).collect();
+        let slots = std::array::from_fn(|p| dicts.index(p));
+        Rarity::Letters { tables, slots }
+    }
+
+    /// Rarity of `word` at position `pos`.
+    pub fn of(&self, word: Word, pos: usize) -> f64 {
+        match self {
+            Rarity::Ranked(ranks) => ranks.get(&word).copied().unwrap_or(1.0),
+            Rarity::Letters { tables, slots } => tables[slots[pos]].rarity(word),
+        }
+    }
+}
+
+/// The measurements behind a difficulty rating.
+#[derive(Debug, Clone)]
+pub struct Rating {
+    /// Number of completions found, up to 2.
+    pub solutions: usize,
+    /// Solver nodes to solve and prove uniqueness.
+    pub nodes: usize,
+    /// Candidate counts at each solver step toward the
+    /// solution.
+    pub branching: Vec<usize>,
+    /// Positions a human could fill by elimination.
+    pub forced: usize,
+    /// Positions a human would have to guess.
+    pub guessed: usize,
+    /// Mean rarity of the words to be found.
+    pub rarity: f64,
+}
+
+impl Rating {
+    /// Score for solver effort: 0 for one node, 1 for a
+    /// hundred thousand or more.
+    pub fn nodes_score(&self) -> f64 {
+        ((self.nodes as f64).log10() / 5.0).clamp(0.0, 1.0)
+    }
+
+    /// Score for mean branching: 0 for forced steps, 1 for
+    /// 256 or more candidates per step.
+    pub fn branching_score(&self) -> f64 {
+        ((self.mean_branching().log2()) / 8.0).clamp(0.0, 1.0)
+    }
+
+    /// Mean number of candidates per solver step.
+    pub fn mean_branching(&self) -> f64 {
+        if self.branching.is_empty() {
+            return 1.0;
+        }
+        let total: usize = self.branching.iter().sum();
+        total as f64 / self.branching.len() as f64
+    }
+
+    /// Score for guessing: the fraction of steps a human
+    /// would have to guess.
+    pub fn guess_score(&self) -> f64 {
+        let steps = self.forced + self.guessed;
+        if steps == 0 {
+            return 0.0;
+        }
+        self.guessed as f64 / steps as f64
+    }
+
+    /// Overall difficulty, 0..100.
+    pub fn difficulty(&self) -> f64 {
+        100.0 * (NODES_WEIGHT * self.nodes_score()
+            + BRANCHING_WEIGHT * self.branching_score()
+            + GUESS_WEIGHT * self.guess_score()
+            + RARITY_WEIGHT * self.rarity)
+    }
+}
+
+impl std::fmt::Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "difficulty {:.1}", self.difficulty())?;
+        if self.solutions > 1 {
+            writeln!(f, "  warning: more than one solution")?;
+        }
+        writeln!(
+            f,
+            "  solver nodes: {} ({:.2})",
+            self.nodes,
+            self.nodes_score(),
+        )?;
+        writeln!(
+            f,
+            "  branching: mean {:.1}, max {} over {} steps ({:.2})",
+            self.mean_branching(),
+            self.branching.iter().max().unwrap_or(&1),
+            self.branching.len(),
+            self.branching_score(),
+        )?;
+        writeln!(
+            f,
+            "  deduction: {} forced, {} guessed ({:.2})",
+            self.forced,
+            self.guessed,
+            self.guess_score(),
+        )?;
+        write!(f, "  word rarity: {:.2}", self.rarity)
+    }
+}
+
+/// The words that could go at position `p` of the square
+/// without blocking any cross position.
+fn candidates<'a>(
+    s: &'a Square,
+    dicts: &'a Dicts,
+    state: &'a AppState,
+    p: usize,
+) -> impl Iterator<Item = Word> + 'a {
+    let target = s.get_pos(p);
+    dicts.get(p).matches(target).filter(move |&w| {
+        let mut t = s.clone();
+        t.set_pos(p, w);
+        state.cross_fit(&t, dicts, p)
+    })
+}
+
+/// Rate a puzzle grid.
+pub fn rate(
+    puzzle: &Square,
+    dicts: &Dicts,
+    doubled: bool,
+    rarity: &Rarity,
+) -> Result<Rating, Error> {
+    // Solve, checking for a second solution.
+    let mut state = AppState::solver(doubled, 2);
+    let mut results = Vec::new();
+    state.find_completions(puzzle, dicts, &mut results);
+    let Some(solution) = results.first() else {
+        bail!("puzzle has no solution");
+    };
+
+    // Follow the solver's choices toward the solution.
+    let mut branching = Vec::new();
+    let mut s = puzzle.clone();
+    while !s.is_full() {
+        let (m, p) = best_pos(&s, dicts, false, 0)
+            .unwrap_or_else(|| (dicts.get(0).into_iter().count(), 0));
+        branching.push(m);
+        s.set_pos(p, solution.get_pos(p));
+    }
+
+    // Solve as a human might: fill a position with only
+    // one candidate if there is one, else guess.
+    let (mut forced, mut guessed) = (0, 0);
+    let mut s = puzzle.clone();
+    while !s.is_full() {
+        let open = (0..10).filter(|&p| {
+            let target = s.get_pos(p);
+            !target.is_empty() && !target.is_full()
+        });
+        let mut only = None;
+        for p in open {
+            let mut cands = candidates(&s, dicts, &state, p);
+            if let (Some(w), None) = (cands.next(), cands.next()) {
+                only = Some((p, w));
+                break;
+            }
+        }
+        match only {
+            Some((p, w)) => {
+                s.set_pos(p, w);
+                forced += 1;
+            }
+            None => {
+                let p = best_pos(&s, dicts, false, 0).map(|(_, p)| p).unwrap_or(0);
+                s.set_pos(p, solution.get_pos(p));
+                guessed += 1;
+            }
+        }
+    }
+
+    // Rate the words that had to be found.
+    let found: Vec<f64> = (0..10)
+        .filter(|&p| !puzzle.get_pos(p).is_full())
+        .map(|p| rarity.of(solution.get_pos(p), p))
+        .collect();
+    let rarity = if found.is_empty() {
+        0.0
+    } else {
+        found.iter().sum::<f64>() / found.len() as f64
+    };
+
+    Ok(Rating {
+        solutions: results.len(),
+        nodes: state.nodes,
+        branching,
+        forced,
+        guessed,
+        rarity,
+    })
+}
+
+impl RateArgs {
+    /// Run the `rate` command: rate each puzzle and show
+    /// the breakdown.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let rarity = match self.ranks {
+            Some(ref path) => {
+                let text = std::fs::read_to_string(path)?;
+                let (words, _) = load_words(&text);
+                Rarity::ranked(&words)
+            }
+            None => Rarity::letters(dicts),
+        };
+
+        for path in &self.puzzles {
+            let puzzle = read_square(path)?;
+            let rating = rate(&puzzle, dicts, args.doubled, &rarity)
+                .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+            println!("{}: {rating}", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rate() {
+    let solution = Square::test();
+    let dicts = Dicts::test(&["abcdz"]);
+    let letters = Rarity::letters(&dicts);
+    // "abcde" has the most usual letters in place, tied
+    // with "abcdz", so no word scores higher.
+    assert_eq!(letters.of(Word::from_str("abcde").unwrap(), 0), 0.0);
+    assert!(letters.of(Word::from_str("fghij").unwrap(), 0) > 0.0);
+    // Every position shares the one dictionary's letters.
+    let Rarity::Letters { ref tables, .. } = letters else {
+        panic!("expected letter rarity");
+    };
+    assert_eq!(tables.len(), 1);
+
+    // A nearly complete puzzle is easy.
+    let mut easy = solution.clone();
+    easy.set_cell(2, 2, 0);
+    let rating = rate(&easy, &dicts, false, &letters).unwrap();
+    assert_eq!(rating.solutions, 1);
+    assert_eq!((rating.forced, rating.guessed), (1, 0));
+    assert_eq!(rating.branching, [1]);
+
+    // An empty puzzle needs guessing, and is not unique.
+    let hard = rate(&Square::default(), &dicts, false, &letters).unwrap();
+    assert_eq!(hard.solutions, 2);
+    assert!(hard.guessed > 0);
+    assert!(hard.difficulty() > rating.difficulty());
+
+    let mut blocked = easy.clone();
+    blocked.set_pos(0, Word::from_str("abcdz").unwrap());
+    assert!(rate(&blocked, &dicts, false, &letters).is_err());
+}
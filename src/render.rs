@@ -0,0 +1,305 @@
+/*!
+Drawing squares for talks and web pages. `ws5 render` draws
+one saved square as an SVG grid, or many as an HTML gallery
+page of SVG grids, as the output file name calls for.
+
+Cells can be highlighted, singly or by whole words. A
+puzzle grid can be given: its given letters are shaded,
+and with `--hide` the other cells are left blank, as for
+solving. Blank cells of a square itself are drawn blank.
+Options show the words across and down beside the grid,
+mark magic squares (whose columns are its rows), and mark
+repeated words.
+*/
+
+use crate::appstate::*;
+use crate::build::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::squares::*;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{Error, bail};
+
+/// Size of a cell, and the margin around the grid.
+const CELL: usize = 40;
+const MARGIN: usize = 10;
+
+/// Width of the word list beside the grid.
+const WORDS_WIDTH: usize = 150;
+
+/// Cell colors.
+const PLAIN: &str = "#ffffff";
+const GIVEN: &str = "#dddddd";
+const HIGHLIGHT: &str = "#ffe680";
+const REPEATED: &str = "#f4b6b6";
+const MAGIC: &str = "#c9a227";
+
+/// Arguments for the `render` command.
+#[derive(clap::Args)]
+pub struct RenderArgs {
+    #[arg(help="file of squares to draw")]
+    pub squares: PathBuf,
+    #[arg(short, long, help="output file: .svg for one square, .html for a gallery")]
+    pub output: PathBuf,
+    #[arg(short, long, default_value_t=0, help="which square in the file to draw as SVG")]
+    pub index: usize,
+    #[arg(short, long, help="most squares to put in a gallery")]
+    pub count: Option<usize>,
+    #[arg(
+        long,
+        value_delimiter=',',
+        help="cells or words to highlight, such as r1c2,c4",
+    )]
+    pub highlight: Vec<String>,
+    #[arg(long, help="puzzle grid whose given letters are shaded")]
+    pub givens: Option<PathBuf>,
+    #[arg(long, requires="givens", help="leave the cells not given blank")]
+    pub hide: bool,
+    #[arg(long, help="show the words across and down")]
+    pub words: bool,
+    #[arg(long, help="mark magic squares")]
+    pub magic: bool,
+    #[arg(long, help="mark repeated words")]
+    pub repeats: bool,
+}
+
+/// How to draw squares.
+#[derive(Debug, Clone, Default)]
+pub struct Style {
+    /// Cells to highlight, as (row, column).
+    pub highlight: HashSet<(usize, usize)>,
+    /// Puzzle grid whose given letters are shaded.
+    pub givens: Option<Square>,
+    /// Leave the cells not given blank.
+    pub hide: bool,
+    /// Show the words across and down.
+    pub words: bool,
+    /// Mark magic squares.
+    pub magic: bool,
+    /// Mark repeated words.
+    pub repeats: bool,
+}
+
+/// The cells, as (row, column), of the word at `pos`.
+fn word_cells(pos: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..5).map(move |i| if pos < 5 { (pos, i) } else { (i, pos - 5) })
+}
+
+/// Parse the cells named by `name`: a cell such as `r1c2`,
+/// or the cells of a word such as `r1` or `c2`.
+pub fn parse_cells(name: &str) -> Result<Vec<(usize, usize)>, Error> {
+    if let Some((r, c)) = name.strip_prefix('r').and_then(|rc| rc.split_once('c')) {
+        match (r.parse::<usize>(), c.parse::<usize>()) {
+            (Ok(r), Ok(c)) if r < 5 && c < 5 => return Ok(vec![(r, c)]),
+            _ => bail!("{name}: cell out of range"),
+        }
+    }
+    Ok(word_cells(parse_pos(name)?).collect())
+}
+
+/// Escape text for XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// The cells of words that appear more than once.
+fn repeated_cells(square: &Square) -> HashSet<(usize, usize)> {
+    let mut positions: HashMap<_, Vec<usize>> = HashMap::new();
+    for pos in 0..10 {
+        let w = square.get_pos(pos);
+        if w.is_full() {
+            positions.entry(w).or_default().push(pos);
+        }
+    }
+    positions
+        .values()
+        .filter(|ps| ps.len() > 1)
+        .flatten()
+        .flat_map(|&pos| word_cells(pos))
+        .collect()
+}
+
+/// Draw a square as SVG.
+pub fn render_svg(square: &Square, style: &Style) -> String {
+    let magic = style.magic && square.is_magic();
+    let repeated = if style.repeats && !magic {
+        repeated_cells(square)
+    } else {
+        HashSet::new()
+    };
+    let grid = 5 * CELL;
+    let width = grid + 2 * MARGIN + if style.words { WORDS_WIDTH } else { 0 };
+    let height = grid + 2 * MARGIN + if magic { 24 } else { 0 };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif">"#,
+    );
+    for r in 0..5 {
+        let letters: Vec<&str> = square.get_pos(r).chars().collect();
+        for (c, &letter) in letters.iter().enumerate() {
+            let given = style.givens.as_ref().is_some_and(|g| g.cell(r, c) != 0);
+            let fill = if style.highlight.contains(&(r, c)) {
+                HIGHLIGHT
+            } else if repeated.contains(&(r, c)) {
+                REPEATED
+            } else if given {
+                GIVEN
+            } else {
+                PLAIN
+            };
+            let (x, y) = (MARGIN + c * CELL, MARGIN + r * CELL);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{x}" y="{y}" width="{CELL}" height="{CELL}" fill="{fill}" stroke="black"/>"#,
+            );
+            let hidden = style.hide && !given;
+            if letter != "." && !hidden {
+                let weight = if given { "bold" } else { "normal" };
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-size="24" font-weight="{weight}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    x + CELL / 2,
+                    y + CELL / 2,
+                    escape(&letter.to_uppercase()),
+                );
+            }
+        }
+    }
+
+    if magic {
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{MARGIN}" y="{MARGIN}" width="{grid}" height="{grid}" fill="none" stroke="{MAGIC}" stroke-width="4"/>"#,
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" font-size="16" fill="{MAGIC}" text-anchor="middle">magic</text>"#,
+            MARGIN + grid / 2,
+            MARGIN + grid + 20,
+        );
+    }
+
+    if style.words {
+        let x = 2 * MARGIN + grid;
+        let mut lines = vec![("across".to_string(), true)];
+        lines.extend((0..5).map(|p| (square.get_pos(p).as_string(), false)));
+        lines.push(("down".to_string(), true));
+        lines.extend((5..10).map(|p| (square.get_pos(p).as_string(), false)));
+        for (i, (line, heading)) in lines.iter().enumerate() {
+            let weight = if *heading { "bold" } else { "normal" };
+            let _ = writeln!(
+                svg,
+                r#"<text x="{x}" y="{}" font-size="14" font-weight="{weight}">{}</text>"#,
+                MARGIN + 14 + i * 16,
+                escape(line),
+            );
+        }
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+/// Draw squares as an HTML gallery page.
+pub fn render_html(squares: &[Square], style: &Style) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>ws5 squares</title>\n\
+         <style>\n\
+         body { font-family: sans-serif; }\n\
+         .gallery { display: flex; flex-wrap: wrap; gap: 1em; }\n\
+         figure { margin: 0; text-align: center; }\n\
+         </style>\n\
+         </head>\n\
+         <body>\n",
+    );
+    let _ = writeln!(html, "<h1>{} squares</h1>", squares.len());
+    html += "<div class=\"gallery\">\n";
+    for (i, s) in squares.iter().enumerate() {
+        let _ = writeln!(
+            html,
+            "<figure>\n{}<figcaption>{i}</figcaption>\n</figure>",
+            render_svg(s, style),
+        );
+    }
+    html += "</div>\n</body>\n</html>\n";
+    html
+}
+
+impl RenderArgs {
+    /// Run the `render` command: draw saved squares.
+    pub fn run(&self, _args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let mut highlight = HashSet::new();
+        for name in &self.highlight {
+            highlight.extend(parse_cells(name)?);
+        }
+        let style = Style {
+            highlight,
+            givens: self.givens.as_deref().map(read_square).transpose()?,
+            hide: self.hide,
+            words: self.words,
+            magic: self.magic,
+            repeats: self.repeats,
+        };
+
+        let squares = read_squares(&self.squares, dicts)?;
+        let output = match self.output.extension().and_then(|e| e.to_str()) {
+            Some("svg") => {
+                let Some(square) = squares.get(self.index) else {
+                    bail!("{}: no square {}", self.squares.display(), self.index);
+                };
+                render_svg(square, &style)
+            }
+            Some("html" | "htm") => {
+                let count = self.count.unwrap_or(squares.len()).min(squares.len());
+                render_html(&squares[..count], &style)
+            }
+            _ => bail!("{}: output must be .svg or .html", self.output.display()),
+        };
+        std::fs::write(&self.output, output)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(parse_cells("r1c2").unwrap(), [(1, 2)]);
+    assert_eq!(parse_cells("c4").unwrap(), [(0, 4), (1, 4), (2, 4), (3, 4), (4, 4)]);
+    assert!(parse_cells("r5c0").is_err());
+    assert!(parse_cells("x1").is_err());
+
+    let magic = Square::from_rows(["aback", "belle", "alloy", "close", "keyed"]);
+    assert!(magic.is_magic());
+    let style = Style { magic: true, words: true, ..Style::default() };
+    let svg = render_svg(&magic, &style);
+    assert_eq!(svg.matches("<rect").count(), 26);
+    assert!(svg.contains(">magic</text>"));
+    assert!(svg.contains(">belle</text>"));
+
+    // A puzzle shows only its givens when hiding.
+    let puzzle = Square::parse("m....\n.....\n.....\n.....\n....a").unwrap();
+    let square = Square::from_rows(["mores", "uvula", "siren", "enact", "delta"]);
+    assert!(!square.is_magic());
+    let style = Style { givens: Some(puzzle), hide: true, ..Style::default() };
+    let svg = render_svg(&square, &style);
+    assert_eq!(svg.matches("<text").count(), 2);
+    assert_eq!(svg.matches(GIVEN).count(), 2);
+
+    // Repeated words are marked.
+    let style = Style { repeats: true, ..Style::default() };
+    let doubled = Square::from_rows(["mores", "mores", "siren", "enact", "delta"]);
+    assert_eq!(repeated_cells(&doubled).len(), 10);
+    assert_eq!(render_svg(&doubled, &style).matches(REPEATED).count(), 10);
+
+    let html = render_html(&[magic, square], &Style::default());
+    assert_eq!(html.matches("<svg").count(), 2);
+}
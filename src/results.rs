@@ -0,0 +1,404 @@
+/*!
+Saving and reading squares, in one of several [Format]s:
+
+* JSON: an array of squares each given as an array of five
+  row strings (or as near-miss objects with a `square`
+  field), possibly wrapped in an object with its
+  [Metadata].
+* NDJSON: the same squares one per line, after a line
+  `{"metadata": ...}` if there is metadata.
+* Text: five-line squares separated by blank lines. Lines
+  starting with `#` are comments, giving the metadata and
+  the misses of near misses.
+* CSV: a header line, then a line of the five rows of each
+  square, and its misses if near misses are saved.
+* Binary: see [crate::binary]. Near misses are not marked,
+  but can be found again.
+
+Reading tells the formats apart by their contents. The
+output path `-` is standard output.
+*/
+
+use crate::binary::*;
+use crate::dict::*;
+use crate::metadata::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Error, anyhow, bail};
+use serde_json::{Value, json};
+
+/// Formats for saved squares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Ndjson,
+    Txt,
+    Csv,
+    Bin,
+}
+
+/// The argument parser needs to know names for the formats.
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self, Error> {
+        match format {
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "txt" => Ok(Format::Txt),
+            "csv" => Ok(Format::Csv),
+            "bin" => Ok(Format::Bin),
+            f => bail!("{f}: unknown format"),
+        }
+    }
+}
+
+impl Format {
+    /// The format a file name calls for, by its extension.
+    /// JSON is the default.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ndjson" | "jsonl") => Format::Ndjson,
+            Some("txt") => Format::Txt,
+            Some("csv") => Format::Csv,
+            Some("bin") => Format::Bin,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// The format to save squares to `path` in: `format`, or
+/// the format the path calls for. Fails if that format
+/// cannot hold `metadata`.
+pub fn output_format(path: &Path, format: Option<Format>, metadata: bool) -> Result<Format, Error> {
+    let format = format.unwrap_or_else(|| Format::for_path(path));
+    if metadata && format == Format::Csv {
+        bail!("metadata cannot be saved as CSV");
+    }
+    Ok(format)
+}
+
+/// True if squares saved to `path` go to standard output.
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Print a summary of a run that saved squares to
+/// `output`: to standard error if the squares went to
+/// standard output.
+pub fn summary(output: &Path, line: impl Display) {
+    if is_stdout(output) {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Make a square from a JSON value.
+pub fn square_from_json(value: &Value) -> Result<Square, Error> {
+    let rows = match value {
+        Value::Object(fields) => fields
+            .get("square")
+            .ok_or_else(|| anyhow!("missing square"))?,
+        v => v,
+    };
+    let Some(rows) = rows.as_array() else {
+        bail!("square must be an array of rows");
+    };
+    let rows: Vec<Word> = rows
+        .iter()
+        .map(|r| {
+            let r = r.as_str().ok_or_else(|| anyhow!("row must be a string"))?;
+            Word::from_str(r)
+        })
+        .collect::<Result<_, _>>()?;
+    let Ok(rows) = rows.try_into() else {
+        bail!("square must have five rows");
+    };
+    Ok(Square::from_words(rows))
+}
+
+/// Saved squares, with their metadata if saved.
+#[derive(Debug, Default)]
+pub struct Results {
+    pub squares: Vec<Square>,
+    pub metadata: Option<Metadata>,
+}
+
+/// Make squares from a JSON array.
+fn squares_from_json(values: &[Value]) -> Result<Vec<Square>, Error> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            square_from_json(v).map_err(|e| anyhow!("square {i}: {e}"))
+        })
+        .collect()
+}
+
+/// Parse JSON or NDJSON squares and any metadata.
+fn parse_json_results(text: &str) -> Result<Results, Error> {
+    // A whole JSON document, unless it is one NDJSON line.
+    if let Ok(mut value) = serde_json::from_str::<Value>(text) {
+        match value {
+            Value::Array(ref values) if !values.first().is_some_and(Value::is_string) => {
+                let squares = squares_from_json(values)?;
+                return Ok(Results { squares, metadata: None });
+            }
+            Value::Object(ref fields) if fields.contains_key("squares") => {
+                let Some(values) = value["squares"].as_array() else {
+                    bail!("squares must be an array");
+                };
+                let squares = squares_from_json(values)?;
+                let metadata = serde_json::from_value(value["metadata"].take())
+                    .map_err(|e| anyhow!("metadata: {e}"))?;
+                return Ok(Results { squares, metadata });
+            }
+            _ => (),
+        }
+    }
+
+    let mut results = Results::default();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(line)
+            .map_err(|e| anyhow!("line {}: {e}", n + 1))?;
+        if let Some(metadata) = value.get("metadata") {
+            let metadata = serde_json::from_value(metadata.clone())
+                .map_err(|e| anyhow!("metadata: {e}"))?;
+            results.metadata = Some(metadata);
+            continue;
+        }
+        let square = square_from_json(&value)
+            .map_err(|e| anyhow!("square {}: {e}", results.squares.len()))?;
+        results.squares.push(square);
+    }
+    Ok(results)
+}
+
+/// Parse CSV squares.
+fn parse_csv_results(text: &str) -> Result<Results, Error> {
+    let mut squares = Vec::new();
+    for line in text.lines().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let rows: Vec<&str> = line.split(',').take(5).collect();
+        let square = Square::parse(&rows.join("\n"))
+            .map_err(|e| anyhow!("square {}: {e}", squares.len()))?;
+        squares.push(square);
+    }
+    Ok(Results { squares, metadata: None })
+}
+
+/// Parse text squares and any metadata.
+fn parse_text_results(text: &str) -> Result<Results, Error> {
+    let mut results = Results::default();
+    let mut block = String::new();
+    // The extra blank line ends the last square.
+    for line in text.lines().chain(std::iter::once("")) {
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(metadata) = comment.trim().strip_prefix("metadata ") {
+                let metadata = serde_json::from_str(metadata)
+                    .map_err(|e| anyhow!("metadata: {e}"))?;
+                results.metadata = Some(metadata);
+            }
+        } else if line.trim().is_empty() {
+            if !block.is_empty() {
+                let square = Square::parse(&block)
+                    .map_err(|e| anyhow!("square {}: {e}", results.squares.len()))?;
+                results.squares.push(square);
+                block.clear();
+            }
+        } else {
+            block += line;
+            block += "\n";
+        }
+    }
+    Ok(results)
+}
+
+/// Parse saved squares and any metadata, in any format.
+pub fn parse_results(text: &str) -> Result<Results, Error> {
+    let start = text.trim_start();
+    if start.starts_with('[') || start.starts_with('{') {
+        parse_json_results(text)
+    } else if start.starts_with("r0,") {
+        parse_csv_results(text)
+    } else {
+        parse_text_results(text)
+    }
+}
+
+/// Parse saved squares in any text format.
+#[cfg(test)]
+pub fn parse_squares(text: &str) -> Result<Vec<Square>, Error> {
+    Ok(parse_results(text)?.squares)
+}
+
+/// Read saved squares from a file, found with `dicts` if
+/// binary.
+pub fn read_squares(path: &Path, dicts: &Dicts) -> Result<Vec<Square>, Error> {
+    Ok(read_results(path, dicts)?.squares)
+}
+
+/// Read saved squares and any metadata from a file, found
+/// with `dicts` if binary.
+pub fn read_results(path: &Path, dicts: &Dicts) -> Result<Results, Error> {
+    let bytes = std::fs::read(path)?;
+    let results = if is_binary(&bytes) {
+        parse_binary(&bytes, dicts)
+    } else {
+        std::str::from_utf8(&bytes)
+            .map_err(Error::from)
+            .and_then(parse_results)
+    };
+    results.map_err(|e| anyhow!("{}: {e}", path.display()))
+}
+
+/// Save squares to `path` in the [output_format()]. With
+/// `near_misses`, each square is marked with its positions
+/// that are not words. With `metadata`, that is saved too.
+pub fn write_squares(
+    path: &Path,
+    format: Option<Format>,
+    squares: &[Square],
+    dicts: &Dicts,
+    near_misses: bool,
+    metadata: Option<&Metadata>,
+) -> Result<(), Error> {
+    let format = output_format(path, format, metadata.is_some())?;
+    let mut save: Box<dyn Write> = if is_stdout(path) {
+        Box::new(BufWriter::new(std::io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    let miss_names = |s: &Square| -> Vec<String> {
+        misses(s, dicts).into_iter().map(pos_name).collect()
+    };
+    let square_json = |s: &Square| {
+        if near_misses {
+            serde_json::to_value(NearMiss::new(s, &misses(s, dicts)))
+        } else {
+            serde_json::to_value(s)
+        }
+    };
+
+    match format {
+        Format::Json => {
+            let squares: Vec<Value> = squares.iter().map(square_json).collect::<Result<_, _>>()?;
+            match metadata {
+                Some(metadata) => serde_json::to_writer(
+                    &mut save,
+                    &json!({ "metadata": metadata, "squares": squares }),
+                )?,
+                None => serde_json::to_writer(&mut save, &squares)?,
+            }
+            if is_stdout(path) {
+                writeln!(save)?;
+            }
+        }
+        Format::Ndjson => {
+            if let Some(metadata) = metadata {
+                writeln!(save, "{}", json!({ "metadata": metadata }))?;
+            }
+            for s in squares {
+                writeln!(save, "{}", square_json(s)?)?;
+            }
+        }
+        Format::Txt => {
+            if let Some(metadata) = metadata {
+                writeln!(save, "# metadata {}\n", serde_json::to_string(metadata)?)?;
+            }
+            for s in squares {
+                if near_misses && !misses(s, dicts).is_empty() {
+                    writeln!(save, "# misses: {}", miss_names(s).join(", "))?;
+                }
+                writeln!(save, "{}\n", s.as_string())?;
+            }
+        }
+        Format::Csv => {
+            let header = if near_misses { "r0,r1,r2,r3,r4,misses" } else { "r0,r1,r2,r3,r4" };
+            writeln!(save, "{header}")?;
+            for s in squares {
+                let rows: Vec<String> = (0..5).map(|p| s.get_pos(p).as_string()).collect();
+                if near_misses {
+                    writeln!(save, "{},{}", rows.join(","), miss_names(s).join(";"))?;
+                } else {
+                    writeln!(save, "{}", rows.join(","))?;
+                }
+            }
+        }
+        Format::Bin => write_binary(&mut save, squares, dicts, metadata)?,
+    }
+    save.flush()?;
+    Ok(())
+}
+
+/// Read a single square, such as a template, from a file.
+pub fn read_square(path: &Path) -> Result<Square, Error> {
+    let text = std::fs::read_to_string(path)?;
+    Square::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))
+}
+
+#[test]
+fn test_parse_squares() {
+    let square = Square::from_rows([
+        "mores",
+        "uvula",
+        "siren",
+        "enact",
+        "delta",
+    ]);
+
+    let json = r#"[["mores","uvula","siren","enact","delta"],
+        {"square":["mores","uvula","siren","enact","delta"],"misses":[]}]"#;
+    assert_eq!(parse_squares(json).unwrap(), [square.clone(), square.clone()]);
+
+    let text = "mores\nuvula\nsiren\nenact\ndelta\n\nmores\nuvula\nsiren\nenact\ndelta";
+    assert_eq!(parse_squares(text).unwrap(), [square.clone(), square.clone()]);
+
+    let wrapped = r#"{"metadata": null, "squares": [["mores","uvula","siren","enact","delta"]]}"#;
+    let results = parse_results(wrapped).unwrap();
+    assert!(results.metadata.is_none());
+    assert_eq!(parse_squares(wrapped).unwrap(), results.squares);
+    assert_eq!(results.squares, [square]);
+
+    assert!(parse_squares("[[\"mores\"]]").is_err());
+    assert!(parse_squares("mores\nuvula\n\nsiren\n").is_err());
+}
+
+#[test]
+fn test_write_squares() {
+    let square = Square::test();
+    // Leave out the last column word, making a near miss.
+    let words = &square.words()[..9];
+    let dicts = Dicts::from(Dict::from_words(words));
+
+    assert_eq!(Format::for_path(Path::new("a.txt")), Format::Txt);
+    assert_eq!(Format::for_path(Path::new("a.jsonl")), Format::Ndjson);
+    assert_eq!(Format::for_path(Path::new("a")), Format::Json);
+    assert!(output_format(Path::new("a.csv"), None, true).is_err());
+
+    let other = Square::from_rows(["mores", "uvula", "siren", "enact", "delta"]);
+    let squares = [square, other];
+    let dir = std::env::temp_dir();
+    for name in ["ws5-test.json", "ws5-test.ndjson", "ws5-test.txt", "ws5-test.csv"] {
+        let path = dir.join(name);
+        write_squares(&path, None, &squares, &dicts, true, None).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(parse_squares(&text).unwrap(), squares, "{name}");
+        assert!(text.contains("col 4"), "{name}");
+    }
+}
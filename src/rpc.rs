@@ -0,0 +1,306 @@
+/*!
+JSON-RPC 2.0 over standard input and output, one message per
+line, for editor plugins. Methods:
+
+* `loadDictionary(path)`: replace the dictionary, returning
+  the number of words.
+* `matches(pattern, slot, limit)`: words matching a pattern
+  such as `"..q.."`, in a slot such as `"r2"` (default
+  `"r0"`).
+* `matchCount(pattern, slot)`: the number of words matching.
+* `complete(grid, limit)`: up to `limit` completions of a
+  grid given as five row strings.
+* `cancel(id)`: stop the request with the given id.
+
+Parameters may be given by name or by position. Requests
+are answered in order by a worker thread, while `cancel`
+is answered at once: a cancelled search stops, and a
+cancelled request still waiting is never started. Either
+way the request is answered with a "request cancelled"
+error.
+*/
+
+use crate::appstate::*;
+use crate::build::*;
+use crate::dict::*;
+use crate::loader::*;
+use crate::serve::*;
+use crate::words::*;
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+
+use serde_json::{Value, json};
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// Default number of matches and completions to return.
+const MATCHES: usize = 100;
+const COMPLETIONS: usize = 10;
+
+/// Arguments for the `rpc` command.
+#[derive(clap::Args)]
+pub struct RpcArgs {}
+
+/// A JSON-RPC error.
+#[derive(Debug)]
+pub struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self { code, message: message.to_string() }
+    }
+}
+
+/// Errors in handling a request are blamed on its
+/// parameters.
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(INVALID_PARAMS, e)
+    }
+}
+
+/// The reply to a request with the given `id`.
+fn reply(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    }
+}
+
+/// Write a message to standard output as one line.
+fn send(message: &Value) {
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{message}");
+    let _ = out.flush();
+}
+
+/// Get parameter `name`, found at `index` if parameters are
+/// given by position.
+fn param<'a>(params: &'a Value, name: &str, index: usize) -> Option<&'a Value> {
+    match params {
+        Value::Array(values) => values.get(index),
+        Value::Object(fields) => fields.get(name),
+        _ => None,
+    }
+    .filter(|v| !v.is_null())
+}
+
+/// Get a string parameter.
+fn string_param<'a>(
+    params: &'a Value,
+    name: &str,
+    index: usize,
+) -> Result<Option<&'a str>, RpcError> {
+    match param(params, name, index) {
+        None => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{name} must be a string"))),
+    }
+}
+
+/// Get a count parameter.
+fn count_param(
+    params: &Value,
+    name: &str,
+    index: usize,
+    default: usize,
+) -> Result<usize, RpcError> {
+    match param(params, name, index) {
+        None => Ok(default),
+        Some(v) => v
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{name} must be a count"))),
+    }
+}
+
+/// The state kept between requests.
+pub struct Rpc {
+    dicts: Dicts,
+    doubled: bool,
+}
+
+impl Rpc {
+    /// Start with the given dictionaries.
+    pub fn new(dicts: Dicts, doubled: bool) -> Self {
+        Self { dicts, doubled }
+    }
+
+    /// The slot and pattern parameters of a match request.
+    fn pattern(&self, params: &Value) -> Result<(&Dict, Word), RpcError> {
+        let pattern = string_param(params, "pattern", 0)?
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing pattern"))?;
+        let slot = string_param(params, "slot", 1)?.unwrap_or("r0");
+        Ok((self.dicts.get(parse_pos(slot)?), Word::from_str(pattern)?))
+    }
+
+    /// Handle a request, stopping early if `cancel` is set.
+    pub fn call(
+        &mut self,
+        method: &str,
+        params: &Value,
+        cancel: &Arc<AtomicBool>,
+    ) -> Result<Value, RpcError> {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(RpcError::new(REQUEST_CANCELLED, "request cancelled"));
+        }
+        match method {
+            "loadDictionary" => {
+                let path = string_param(params, "path", 0)?
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing path"))?;
+                let text = std::fs::read_to_string(path).map_err(anyhow::Error::from)?;
+                let (words, report) = load_words(&text);
+                if report.has_errors() {
+                    return Err(RpcError::new(INVALID_PARAMS, format!("{path}: {report}")));
+                }
+                self.dicts = Dicts::from(Dict::from_words(&words));
+                Ok(json!(words.len()))
+            }
+            "matches" => {
+                let (dict, target) = self.pattern(params)?;
+                let limit = count_param(params, "limit", 2, MATCHES)?;
+                let words: Vec<Word> = if target.is_empty() {
+                    dict.into_iter().copied().take(limit).collect()
+                } else {
+                    dict.matches(target).take(limit).collect()
+                };
+                let words: Vec<String> = words.iter().map(|w| w.as_string()).collect();
+                Ok(json!(words))
+            }
+            "matchCount" => {
+                let (dict, target) = self.pattern(params)?;
+                if target.is_empty() {
+                    return Ok(json!(dict.into_iter().count()));
+                }
+                Ok(json!(dict.match_count(target)))
+            }
+            "complete" => {
+                let grid = grid_from_json(param(params, "grid", 0))?;
+                let limit = count_param(params, "limit", 1, COMPLETIONS)?;
+                let mut state = AppState::solver(self.doubled, limit);
+                state.cancel = Some(Arc::clone(cancel));
+                let mut results = Vec::new();
+                state.find_completions(&grid, &self.dicts, &mut results);
+                if state.stop == Some(Stop::Cancelled) {
+                    return Err(RpcError::new(REQUEST_CANCELLED, "request cancelled"));
+                }
+                Ok(json!({ "squares": results, "nodes": state.nodes }))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("{method}: no such method"))),
+        }
+    }
+}
+
+/// A request waiting for the worker.
+struct Job {
+    id: Value,
+    method: String,
+    params: Value,
+    cancel: Arc<AtomicBool>,
+}
+
+impl RpcArgs {
+    /// Run the `rpc` command: answer requests on standard
+    /// input until it is closed.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), anyhow::Error> {
+        // Cancel flags of requests not yet answered, by id.
+        let pending: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> = Arc::default();
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let mut rpc = Rpc::new(dicts.clone(), args.doubled);
+        let worker_pending = Arc::clone(&pending);
+        let worker = std::thread::spawn(move || {
+            for job in receiver {
+                let result = rpc.call(&job.method, &job.params, &job.cancel);
+                worker_pending.lock().unwrap().remove(&job.id.to_string());
+                // Notifications get no reply.
+                if !job.id.is_null() {
+                    send(&reply(&job.id, result));
+                }
+            }
+        });
+
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    send(&reply(&Value::Null, Err(RpcError::new(PARSE_ERROR, e))));
+                    continue;
+                }
+            };
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let Some(method) = request.get("method").and_then(|m| m.as_str()) else {
+                send(&reply(&id, Err(RpcError::new(INVALID_REQUEST, "missing method"))));
+                continue;
+            };
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+            if method == "cancel" {
+                let found = param(&params, "id", 0)
+                    .and_then(|target| pending.lock().unwrap().get(&target.to_string()).cloned())
+                    .map(|cancel| cancel.store(true, Ordering::Relaxed))
+                    .is_some();
+                if !id.is_null() {
+                    send(&reply(&id, Ok(json!(found))));
+                }
+                continue;
+            }
+
+            let cancel = Arc::new(AtomicBool::new(false));
+            if !id.is_null() {
+                pending.lock().unwrap().insert(id.to_string(), Arc::clone(&cancel));
+            }
+            let method = method.to_string();
+            sender.send(Job { id, method, params, cancel })?;
+        }
+
+        drop(sender);
+        let _ = worker.join();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rpc() {
+    let mut rpc = Rpc::new(Dicts::test(&[]), false);
+    let go = Arc::new(AtomicBool::new(false));
+
+    let result = rpc.call("matches", &json!(["a...."]), &go).unwrap();
+    assert_eq!(result, json!(["abcde", "afkpu"]));
+    let result = rpc.call("matchCount", &json!({"pattern": "....."}), &go).unwrap();
+    assert_eq!(result, json!(10));
+
+    let params = json!({"grid": ["abcde", ".....", ".....", ".....", "....."]});
+    let result = rpc.call("complete", &params, &go).unwrap();
+    assert_eq!(result["squares"][0][4], "uvwxy");
+
+    let stop = Arc::new(AtomicBool::new(true));
+    let err = rpc.call("complete", &params, &stop).unwrap_err();
+    assert_eq!(err.code, REQUEST_CANCELLED);
+
+    let err = rpc.call("nothing", &json!([]), &go).unwrap_err();
+    assert_eq!(err.code, METHOD_NOT_FOUND);
+    let err = rpc.call("matches", &json!([]), &go).unwrap_err();
+    assert_eq!(err.code, INVALID_PARAMS);
+}
@@ -11,6 +11,9 @@ Theory of operation:
       4
 
 * Start by placing a word in position 1.
+* With near misses allowed, also start by placing a word in
+  each later position, leaving the positions before it as
+  misses.
 * Continue by finding a most-constrained not-full
   position for the next placement.
 * For every word that might be placed there, check that it
@@ -23,39 +26,98 @@ Theory of operation:
 use crate::appstate::*;
 use crate::squares::*;
 use crate::dict::*;
+use crate::words::*;
+
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
 /// Report the number of most-constrained next-word position
 /// matches, and the position. Returns [None] if no
 /// placement is possible.
-// XXX Used to have a gratuitous collect().
-fn best_pos(s: &Square, dict: &Dict) -> Option<(usize, usize)> {
-    (0..10)
+/// When near misses are allowed, positions with no matches
+/// are skipped, to be filled by crossing words, and so are
+/// the positions before `seed`. Otherwise positions with
+/// no matches are reported like any other, for the caller
+/// to catch.
+pub fn best_pos(
+    s: &Square,
+    dicts: &Dicts,
+    misses: bool,
+    seed: usize,
+) -> Option<(usize, usize)> {
+    // XXX Used to have a gratuitous collect().
+    let counts = (0..10)
         .map(|p| (p, s.get_pos(p)))
         .filter(|(_, target)| !target.is_empty() && !target.is_full())
-        .map(|(p, target)| (dict.match_count(target), p))
-        .min()
+        .map(|(p, target)| (dicts.get(p).match_count(target), p));
+    if misses {
+        counts.filter(|&(m, p)| m > 0 && p >= seed).min()
+    } else {
+        counts.min()
+    }
+}
+
+/// List the positions of the square that cannot be filled
+/// with a dictionary word: the "misses".
+pub fn misses(s: &Square, dicts: &Dicts) -> Vec<usize> {
+    (0..10)
+        .filter(|&p| {
+            let target = s.get_pos(p);
+            !target.is_empty() && !dicts.get(p).is_fit(std::iter::once(target))
+        })
+        .collect()
+}
+
+/// Count the misses of the square. See [misses()].
+fn count_misses(s: &Square, dicts: &Dicts) -> usize {
+    if dicts.is_shared(0..10) {
+        let targets = (0..10)
+            .map(|p| s.get_pos(p))
+            .filter(|target| !target.is_empty());
+        dicts.get(0).count_misfits(targets)
+    } else {
+        misses(s, dicts).len()
+    }
 }
 
 /// Verify that all cross-targets of position `pos` can be
 /// legally filled with something, under the given constraints.
+/// With near misses allowed, instead verify that no more
+/// than `max_misses` positions anywhere are misses.
 // XXX The order of tests here matters a bit.
 fn cross_fit(
     s: &Square,
-    dict: &Dict,
+    dicts: &Dicts,
     pos: usize,
     doubled: bool,
     transposed: bool,
+    max_misses: usize,
 ) -> bool {
     if !transposed && s.is_transposed() {
         return false;
     }
 
+    if max_misses > 0 {
+        return count_misses(s, dicts) <= max_misses
+            && (doubled || !s.has_double());
+    }
+
     let range = if pos < 5 {
         5..10
     } else {
         0..5
     };
-    if !dict.is_fit(range.map(|p| s.get_pos(p))) {
+    // Check the cross positions together when they share a
+    // dictionary, which is the usual case.
+    let fit = if dicts.is_shared(range.clone()) {
+        let dict = dicts.get(range.start);
+        dict.is_fit(range.map(|p| s.get_pos(p)))
+    } else {
+        range
+            .into_iter()
+            .all(|p| dicts.get(p).is_fit(std::iter::once(s.get_pos(p))))
+    };
+    if !fit {
         return false;
     }
 
@@ -67,24 +129,165 @@ fn cross_fit(
 }
 
 impl AppState {
+    /// Accumulate all the word squares that complete the
+    /// given template in `results`. Returns [false] if
+    /// terminating early, [true] otherwise.
+    pub fn find_completions(
+        &mut self,
+        template: &Square,
+        dicts: &Dicts,
+        results: &mut Vec<Square>,
+    ) -> bool {
+        // The search assumes that every position it has
+        // touched can be filled, so check that the template
+        // gets it started that way.
+        let viable = count_misses(template, dicts) <= self.max_misses;
+        if !viable
+            || (!self.doubled && template.has_double())
+            || (!self.transposed && template.is_transposed())
+        {
+            return true;
+        }
+        self.first_row = None;
+
+        // Pay for the template letters out of a copy of the
+        // budget, since a search that stops early does not
+        // refund what it has charged. The state can then be
+        // used for another search.
+        let budget = self.budget.clone();
+        let empty = Word::default();
+        let paid = (0..5).all(|p| self.take_letters(empty, template.get_pos(p)));
+        let done = if paid {
+            let mut s = template.clone();
+            self.find_all(&mut s, dicts, results)
+        } else {
+            true
+        };
+        self.budget = budget;
+        done
+    }
+
+    /// [cross_fit()] under the current settings.
+    pub fn cross_fit(&self, s: &Square, dicts: &Dicts, pos: usize) -> bool {
+        cross_fit(s, dicts, pos, self.doubled, self.transposed, self.max_misses)
+    }
+
+    /// Charge the letter budget, if any, for placing `w`
+    /// over `target`. Returns [false] if over budget.
+    fn take_letters(&mut self, target: Word, w: Word) -> bool {
+        self.budget.as_mut().is_none_or(|b| b.take(target, w))
+    }
+
+    /// Undo [AppState::take_letters()].
+    fn give_back_letters(&mut self, target: Word, w: Word) {
+        if let Some(b) = self.budget.as_mut() {
+            b.give_back(target, w);
+        }
+    }
+
+    /// Accumulate the near misses of [AppState::find_all()]
+    /// whose first word is past the first row, starting
+    /// from the empty square `s`: for each position in
+    /// turn, place each word there, leaving the positions
+    /// before it as misses. Returns [false] if terminating
+    /// early, [true] otherwise.
+    fn find_seeded(
+        &mut self,
+        s: &mut Square,
+        dicts: &Dicts,
+        results: &mut Vec<Square>,
+    ) -> bool {
+        let empty = Word::default();
+        for q in 1..=self.max_misses.min(9) {
+            self.seed = q;
+            for &w in dicts.get(q) {
+                if !self.take_letters(empty, w) {
+                    continue;
+                }
+                s.set_pos(q, w);
+                if self.cross_fit(s, dicts, q) && !self.find_all(s, dicts, results) {
+                    return false;
+                }
+                s.set_pos(q, empty);
+                self.give_back_letters(empty, w);
+            }
+        }
+        true
+    }
+
     /// Accumulate all the word squares under the given
     /// constraints in `results`. Returns [false] if
     /// terminating early, [true] otherwise.
     pub fn find_all(
         &mut self,
         s: &mut Square,
-        dict: &Dict,
+        dicts: &Dicts,
         results: &mut Vec<Square>,
     ) -> bool {
         self.nodes += 1;
 
+        // Give up if cancelled or out of time or nodes. Checking
+        // the clock is slow, so only check it now and then.
+        if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+            self.stop = Some(Stop::Cancelled);
+            return false;
+        }
+        if let Some(deadline) = self.deadline
+            && self.nodes.is_multiple_of(1024)
+            && Instant::now() >= deadline
+        {
+            self.stop = Some(Stop::TimedOut);
+            return false;
+        }
+        if self.max_nodes.is_some_and(|m| self.nodes > m) {
+            self.stop = Some(Stop::MaxNodes);
+            return false;
+        }
+        if let Some(progress) = &mut self.progress
+            && self.nodes.is_multiple_of(1024)
+        {
+            progress.update(self.first_row, s.get_pos(0), self.nodes, results.len());
+        }
+
         // Initial case: place a word in the first row and recurse.
-        if s.get_pos(0).is_empty() {
-            for &w in dict {
+        if s.is_empty() {
+            let empty = Word::default();
+            let range = self.first_rows.clone().unwrap_or(0..usize::MAX);
+            let words = dicts
+                .get(0)
+                .into_iter()
+                .enumerate()
+                .take(range.end)
+                .skip(range.start);
+            for (i, &w) in words {
+                self.first_row = Some(i);
+                if let Some(progress) = &mut self.progress {
+                    progress.next_word(self.nodes);
+                }
+                if !self.take_letters(empty, w) {
+                    continue;
+                }
                 s.set_pos(0, w);
-                if !self.find_all(s, dict, results) {
+                // A letter that starts no column word leaves a
+                // column with no matches, which the recursive
+                // case cannot handle, so check the columns as
+                // for any other placement.
+                if self.cross_fit(s, dicts, 0) && !self.find_all(s, dicts, results) {
                     return false;
                 }
+                self.give_back_letters(empty, w);
+            }
+            s.set_pos(0, empty);
+
+            // A near miss may have a non-word first row, so
+            // also search from each later position. These
+            // searches go with the first shard of first-row
+            // words.
+            if self.max_misses > 0 && range.start == 0 {
+                self.first_row = None;
+                let done = self.find_seeded(s, dicts, results);
+                self.seed = 0;
+                return done;
             }
             return true;
         }
@@ -96,17 +299,24 @@ impl AppState {
                 assert!(!s.has_double(), "{}", s.as_string());
             }
 
+            // A square with a word before its seed position
+            // is found from that position instead.
+            let seen = (0..self.seed)
+                .any(|p| dicts.get(p).is_fit(std::iter::once(s.get_pos(p))));
+            if seen {
+                return true;
+            }
+
             // Save the solution.
             results.push(s.clone());
 
             // Show progress according to style.
             match self.trace {
-                TraceStyle::None => (),
+                TraceStyle::None | TraceStyle::Status => (),
                 TraceStyle::Short => {
-                    if results.len() % 100 == 0 {
+                    if results.len().is_multiple_of(100) {
                         let tr: String = s
                             .get_pos(0)
-                            .as_string()
                             .chars()
                             .take(2)
                             .collect();
@@ -114,26 +324,38 @@ impl AppState {
                     }
                 },
                 TraceStyle::Full => eprintln!("{}\n", s.as_string()),
+                TraceStyle::Json { solutions: true } => {
+                    if let Some(ref progress) = self.progress {
+                        progress.solution(s, self.nodes, results.len());
+                    }
+                },
+                TraceStyle::Json { solutions: false } => (),
             }
 
             // If enough solutions have been found, bail.
-            match self.limit {
-                Some(limit) => return results.len() < limit,
-                None => return true,
+            if self.limit.is_some_and(|limit| results.len() >= limit) {
+                self.stop = Some(Stop::Limit);
+                return false;
             }
+            return true;
         }
 
         // Recursive case: Try to place a word, then try to solve the rest.
 
         // Find the placement position.
-        let p = if let Some((m, p)) = best_pos(s, dict) {
-            // Safety checks.
+        let p = if let Some((m, p)) = best_pos(s, dicts, self.max_misses > 0, self.seed) {
+            // Safety checks. A search from scratch places
+            // the first row before anything else.
             if m == 0 {
                 panic!("internal error: best_pos 0:\n{}\n", s.as_string());
             }
-            assert!(p > 0);
+            assert!(p > 0 || self.first_row.is_none());
 
             p
+        } else if self.max_misses > 0 {
+            // Every open position is a miss, so the blanks
+            // can never be filled.
+            return true;
         } else {
             panic!("internal error: best_pos None:\n{}\n", s.as_string());
         };
@@ -141,24 +363,30 @@ impl AppState {
         // Try to solve the rest. Get possible next words
         // and see if they fit. If so, recurse.
         let target = s.get_pos(p);
-        for w in dict.matches(target) {
+        for w in dicts.get(p).matches(target) {
+            // Pay for the new letters.
+            if !self.take_letters(target, w) {
+                continue;
+            }
+
             // Place the word.
             s.set_pos(p, w);
 
             // Check for fit.
-            let fit = cross_fit(s, dict, p, self.doubled, self.transposed);
+            let fit = self.cross_fit(s, dicts, p);
 
             #[allow(clippy::collapsible_if)]
             // I find this much more readable
             if fit {
                 // Recurse.
-                if !self.find_all(s, dict, results) {
+                if !self.find_all(s, dicts, results) {
                     return false;
                 }
             }
 
             // Undo the placement.
             s.set_pos(p, target);
+            self.give_back_letters(target, w);
         }
 
         true
@@ -167,8 +395,6 @@ impl AppState {
 
 #[test]
 fn test_fitting() {
-    use crate::words::Word;
-
     let mut s = Square::from_rows([
         "abcde",
         "fghij",
@@ -182,35 +408,187 @@ fn test_fitting() {
         .collect();
     let mut dict = Dict::from_words(words.as_ref());
     dict.add_str("fzzij").unwrap();
+    let dict = Dicts::from(dict);
 
     let word = Word::from_str("...ij").unwrap();
     s.set_pos(1, word);
     let word = Word::from_str("k.mno").unwrap();
     s.set_pos(2, word);
 
-    let (_, p) = best_pos(&s, &dict).unwrap();
+    let (_, p) = best_pos(&s, &dict, false, 0).unwrap();
     assert!(p == 2, "{}", p);
     assert!(!s.is_transposed());
 
-    s.set_coord(1, 0, 'a');
+    s.set_coord(1, 0, "a");
     assert!(s.is_transposed());
-    s.set_coord(1, 0, 'f');
-    let (_, p) = best_pos(&s, &dict).unwrap();
+    s.set_coord(1, 0, "f");
+    let (_, p) = best_pos(&s, &dict, false, 0).unwrap();
     assert!(p == 2, "{}", p);
     assert!(!s.is_transposed());
-    assert!(cross_fit(&s, &dict, 5, false, false), "{}", s.as_string());
+    assert!(cross_fit(&s, &dict, 5, false, false, 0), "{}", s.as_string());
 
     let word = words[6];
     assert!(s.is_fit(6, word));
     s.set_pos(6, word);
-    assert!(cross_fit(&s, &dict, 6, false, false), "{}", s.as_string());
+    assert!(cross_fit(&s, &dict, 6, false, false, 0), "{}", s.as_string());
 
     let word = words[7];
     assert!(s.is_fit(7, word));
     s.set_pos(7, word);
-    assert!(cross_fit(&s, &dict, 7, false, false), "{}", s.as_string());
+    assert!(cross_fit(&s, &dict, 7, false, false, 0), "{}", s.as_string());
 
     let word = Word::from_str("aakpu").unwrap();
     s.set_pos(5, word);
-    assert!(!cross_fit(&s, &dict, 7, true, false), "{}", s.as_string());
+    assert!(!cross_fit(&s, &dict, 7, true, false, 0), "{}", s.as_string());
+}
+
+#[test]
+fn test_first_row_fit() {
+    let square = Square::test();
+    // Starts no column word, so must be passed over as a
+    // first row rather than searched under.
+    let dicts = Dicts::test(&["zzzzz"]);
+
+    let mut state = AppState::default();
+    let mut results = Vec::new();
+    assert!(state.find_completions(&Square::default(), &dicts, &mut results));
+    assert_eq!(results, [square]);
+}
+
+#[test]
+fn test_split_dicts() {
+    let square = Square::test();
+    let words = square.words();
+    let (rows, cols) = words.split_at(5);
+
+    // The rows alone make no square.
+    let mut state = AppState::default();
+    let dicts = Dicts::from(Dict::from_words(rows));
+    let mut results = Vec::new();
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert!(results.is_empty());
+
+    // Rows across and columns down make exactly one.
+    let dicts = Dicts::split(Dict::from_words(rows), Dict::from_words(cols));
+    let mut results = Vec::new();
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert_eq!(results, [square]);
+}
+
+#[test]
+fn test_template() {
+    let square = Square::test();
+    let dicts = Dicts::test(&["abcdz", "ezjoy"]);
+
+    let mut state = AppState::default();
+
+    // A template fixing the last letter picks out the square.
+    let template = Square::parse(".....\n.....\n.....\n.....\n....y").unwrap();
+    let mut results = Vec::new();
+    state.find_completions(&template, &dicts, &mut results);
+    assert_eq!(results, [square]);
+
+    // A template letter that fits nothing gives nothing.
+    let template = Square::parse(".....\n.....\n..q..\n.....\n.....").unwrap();
+    let mut results = Vec::new();
+    state.find_completions(&template, &dicts, &mut results);
+    assert!(results.is_empty());
+}
+
+#[test]
+fn test_near_miss() {
+    let square = Square::test();
+
+    // Leave out the last column word.
+    let words = &square.words()[..9];
+    let dicts = Dicts::from(Dict::from_words(words));
+
+    let mut state = AppState::default();
+    let mut results = Vec::new();
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert!(results.is_empty());
+
+    state.max_misses = 1;
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert_eq!(misses(&square, &dicts), [9]);
+    assert_eq!(results, std::slice::from_ref(&square));
+
+    // Without near misses, a position with no matches is
+    // still the best position, so that it gets caught.
+    let mut s = Square::default();
+    s.set_pos(0, square.get_pos(0));
+    assert_eq!(best_pos(&s, &dicts, false, 0), Some((0, 9)));
+    assert_ne!(best_pos(&s, &dicts, true, 0), Some((0, 9)));
+}
+
+#[test]
+fn test_first_row_miss() {
+    let square = Square::test();
+
+    // Leave out the first row word.
+    let words = &square.words()[1..];
+    let dicts = Dicts::from(Dict::from_words(words));
+
+    let mut state = AppState::default();
+    let mut results = Vec::new();
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert!(results.is_empty());
+
+    state.max_misses = 1;
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert_eq!(misses(&square, &dicts), [0]);
+    assert_eq!(results, std::slice::from_ref(&square));
+
+    // It is found whether or not transposes are kept.
+    state.transposed = true;
+    let mut results = Vec::new();
+    state.find_all(&mut Square::default(), &dicts, &mut results);
+    assert!(results.contains(&square));
+}
+
+#[test]
+fn test_budget() {
+    let text = std::fs::read_to_string("small-dict.txt").unwrap();
+    let (words, _) = crate::loader::load_words(&text);
+    let dicts = Dicts::from(Dict::from_words(&words));
+
+    let mut whole = Vec::new();
+    let mut state = AppState::default();
+    assert!(state.find_completions(&Square::default(), &dicts, &mut whole));
+    assert_eq!(state.stop, None);
+
+    // The node budget stops the search partway, having
+    // found some of the squares.
+    let max_nodes = state.nodes / 2;
+    let mut state = AppState { max_nodes: Some(max_nodes), ..AppState::default() };
+    let mut part = Vec::new();
+    assert!(!state.find_completions(&Square::default(), &dicts, &mut part));
+    assert_eq!(state.stop, Some(Stop::MaxNodes));
+    assert_eq!(state.nodes, max_nodes + 1);
+    assert!(whole.starts_with(&part));
+    let first_row = state.first_row.unwrap();
+    assert!(first_row > 0 && first_row < words.len());
+
+    // A deadline already past stops it too.
+    let deadline = Some(Instant::now());
+    let mut state = AppState { deadline, ..AppState::default() };
+    state.find_completions(&Square::default(), &dicts, &mut part);
+    assert_eq!(state.stop, Some(Stop::TimedOut));
+}
+
+#[test]
+fn test_letter_budget_reuse() {
+    let square = Square::test();
+    let dicts = Dicts::test(&[]);
+    let template = Square::parse("abcde\n.....\n.....\n.....\n.....").unwrap();
+
+    // The budget is whole again after a search, even one
+    // stopped by the solution limit.
+    let mut state = AppState::solver(false, 1);
+    state.budget = Some(crate::budget::LetterBudget::parse("abcdefghijklmnopqrstuvwxy", true).unwrap());
+    for _ in 0..2 {
+        let mut results = Vec::new();
+        state.find_completions(&template, &dicts, &mut results);
+        assert_eq!(results, std::slice::from_ref(&square));
+    }
 }
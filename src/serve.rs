@@ -0,0 +1,343 @@
+/*!
+A local HTTP query service, so that other programs such as
+a puzzle editor can ask for completions without starting a
+search process each time. The dictionaries are loaded once.
+Each request thread has its own copy of them, since their
+caches cannot be shared between threads.
+
+Every request is a `POST` with a JSON body, answered with
+JSON. Grids and squares are arrays of five row strings,
+with `.` for blanks.
+
+* `/complete` takes `{"grid": [...], "limit": 10}` and
+  returns `{"squares": [...], "nodes": N, "timed_out": false}`.
+* `/candidates` takes `{"slot": "r2", "pattern": "..q..",
+  "limit": 100}` and returns `{"count": N, "words": [...]}`.
+* `/verify` takes `{"square": [...]}` and returns
+  `{"valid": true, "misses": [], "repeated": false}`.
+
+Errors are answered with a 4xx status and `{"error": ...}`.
+Limits must be at least 1, and at most 1000 squares or
+words are returned. Searches stop at the time limit,
+returning what they have found so far. A request that
+panics is dropped without taking down the service.
+*/
+
+use crate::appstate::*;
+use crate::build::*;
+use crate::dict::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, anyhow, bail};
+use serde_json::{Value, json};
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: usize = 1 << 16;
+
+/// Longest request or header line accepted, in bytes, and
+/// most header lines.
+const MAX_LINE: usize = 8192;
+const MAX_HEADERS: usize = 100;
+
+/// Default and largest number of completions to return.
+const COMPLETIONS: usize = 10;
+const MAX_COMPLETIONS: usize = 1000;
+
+/// Default and largest number of candidates to return.
+const CANDIDATES: usize = 100;
+const MAX_CANDIDATES: usize = 1000;
+
+/// How long to wait on a slow client.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Arguments for the `serve` command.
+#[derive(clap::Args)]
+pub struct ServeArgs {
+    #[arg(long, default_value="127.0.0.1:8080", help="address to listen on")]
+    pub listen: String,
+    #[arg(long, default_value_t=4, help="number of requests to handle at once")]
+    pub threads: usize,
+    #[arg(long, default_value_t=10.0, help="time limit per request, in seconds")]
+    pub time_limit: f64,
+}
+
+/// Settings shared by the request handlers.
+struct Service {
+    doubled: bool,
+    time_limit: Duration,
+}
+
+/// Make a square from a JSON array of row strings.
+pub fn grid_from_json(value: Option<&Value>) -> Result<Square, Error> {
+    let rows = value
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow!("expected an array of five rows"))?;
+    let rows: Vec<&str> = rows
+        .iter()
+        .map(|r| r.as_str().ok_or_else(|| anyhow!("rows must be strings")))
+        .collect::<Result<_, _>>()?;
+    Square::parse(&rows.join("\n"))
+}
+
+/// Get an optional count from a request.
+fn limit(request: &Value, default: usize) -> Result<usize, Error> {
+    match request.get("limit") {
+        None => Ok(default),
+        Some(n) => {
+            match n.as_u64() {
+                Some(0) => bail!("limit must be at least 1"),
+                Some(n) => Ok(n as usize),
+                None => bail!("limit must be a count"),
+            }
+        }
+    }
+}
+
+impl Service {
+    /// Complete a partial grid.
+    fn complete(&self, request: &Value, dicts: &Dicts) -> Result<Value, Error> {
+        let grid = grid_from_json(request.get("grid"))?;
+        let limit = limit(request, COMPLETIONS)?.min(MAX_COMPLETIONS);
+        let mut state = AppState::solver(self.doubled, limit);
+        state.deadline = Some(Instant::now() + self.time_limit);
+        let mut results = Vec::new();
+        state.find_completions(&grid, dicts, &mut results);
+        Ok(json!({
+            "squares": results,
+            "nodes": state.nodes,
+            "timed_out": state.stop == Some(Stop::TimedOut),
+        }))
+    }
+
+    /// List the words matching a pattern in a slot.
+    fn candidates(&self, request: &Value, dicts: &Dicts) -> Result<Value, Error> {
+        let slot = request
+            .get("slot")
+            .and_then(|s| s.as_str())
+            .ok_or_else(|| anyhow!("missing slot"))?;
+        let pattern = request
+            .get("pattern")
+            .and_then(|s| s.as_str())
+            .unwrap_or(".....");
+        let dict = dicts.get(parse_pos(slot)?);
+        let target = Word::from_str(pattern)?;
+        let limit = limit(request, CANDIDATES)?.min(MAX_CANDIDATES);
+        let (count, words): (usize, Vec<String>) = if target.is_empty() {
+            let words = dict.into_iter().take(limit).map(|w| w.as_string()).collect();
+            (dict.into_iter().count(), words)
+        } else {
+            let words = dict.matches(target).take(limit).map(|w| w.as_string()).collect();
+            (dict.match_count(target), words)
+        };
+        Ok(json!({ "count": count, "words": words }))
+    }
+
+    /// Check a square.
+    fn verify(&self, request: &Value, dicts: &Dicts) -> Result<Value, Error> {
+        let square = grid_from_json(request.get("square"))?;
+        if !square.is_full() {
+            bail!("square has blanks");
+        }
+        let misses: Vec<String> = misses(&square, dicts).into_iter().map(pos_name).collect();
+        let repeated = !self.doubled && square.has_double();
+        Ok(json!({
+            "valid": misses.is_empty() && !repeated,
+            "misses": misses,
+            "repeated": repeated,
+        }))
+    }
+
+    /// Answer a request, giving the HTTP status and the
+    /// JSON reply.
+    fn handle(&self, method: &str, path: &str, body: &str, dicts: &Dicts) -> (u16, Value) {
+        type Handler = fn(&Service, &Value, &Dicts) -> Result<Value, Error>;
+        let handler: Handler = match path {
+            "/complete" => Service::complete,
+            "/candidates" => Service::candidates,
+            "/verify" => Service::verify,
+            _ => return (404, json!({ "error": format!("{path}: no such endpoint") })),
+        };
+        if method != "POST" {
+            return (405, json!({ "error": "use POST" }));
+        }
+        let reply = serde_json::from_str(body)
+            .map_err(Error::from)
+            .and_then(|request| handler(self, &request, dicts));
+        match reply {
+            Ok(reply) => (200, reply),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        }
+    }
+
+    /// Read a request and answer it, giving the HTTP status
+    /// and the JSON reply.
+    fn request(&self, input: &mut impl BufRead, dicts: &Dicts) -> Result<(u16, Value), Error> {
+        let Some(line) = read_line(input)? else {
+            return Ok((400, json!({ "error": "request line too long" })));
+        };
+        let mut fields = line.split_whitespace();
+        let (Some(method), Some(path)) = (fields.next(), fields.next()) else {
+            bail!("bad request line");
+        };
+
+        let mut length = 0;
+        for n in 0.. {
+            let header = match read_line(input)? {
+                Some(header) if n < MAX_HEADERS => header,
+                _ => return Ok((431, json!({ "error": "request headers too large" }))),
+            };
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                length = value.trim().parse()?;
+            }
+        }
+
+        if length > MAX_BODY {
+            return Ok((413, json!({ "error": "request too large" })));
+        }
+        let mut body = vec![0; length];
+        input.read_exact(&mut body)?;
+        Ok(self.handle(method, path, &String::from_utf8_lossy(&body), dicts))
+    }
+
+    /// Read a request from a connection and answer it.
+    fn respond(&self, stream: TcpStream, dicts: &Dicts) -> Result<(), Error> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut input = BufReader::new(stream.try_clone()?);
+        let (status, reply) = self.request(&mut input, dicts)?;
+
+        let reason = match status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Content Too Large",
+            431 => "Request Header Fields Too Large",
+            _ => "Bad Request",
+        };
+        let reply = reply.to_string();
+        let mut output = stream;
+        write!(
+            output,
+            "HTTP/1.1 {status} {reason}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{reply}",
+            reply.len(),
+        )?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a line of at most [MAX_LINE] bytes, or [None] if it
+/// is longer.
+fn read_line(input: &mut impl BufRead) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    input.take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.len() > MAX_LINE {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+impl ServeArgs {
+    /// Run the `serve` command: answer requests until
+    /// killed.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let service = Arc::new(Service {
+            doubled: args.doubled,
+            time_limit: Duration::try_from_secs_f64(self.time_limit)?,
+        });
+        let listener = TcpListener::bind(&self.listen)?;
+        eprintln!("serving on http://{}", self.listen);
+
+        // Hand connections to a pool of threads, each with
+        // its own dictionaries.
+        let (sender, receiver) = mpsc::channel::<TcpStream>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.threads.max(1) {
+            let dicts = dicts.clone();
+            let service = Arc::clone(&service);
+            let receiver = Arc::clone(&receiver);
+            std::thread::spawn(move || loop {
+                // The lock is only held to receive, so a
+                // poisoned lock still guards a good channel.
+                let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                let Ok(stream) = next else {
+                    return;
+                };
+                let reply = catch_unwind(AssertUnwindSafe(|| service.respond(stream, &dicts)));
+                match reply {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => eprintln!("ws5: request: {e}"),
+                    Err(_) => eprintln!("ws5: request panicked"),
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => sender.send(stream)?,
+                Err(e) => eprintln!("ws5: {e}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_serve() {
+    let dicts = Dicts::test(&[]);
+    let service = Service { doubled: false, time_limit: Duration::from_secs(10) };
+
+    let body = r#"{"grid": ["abcde", ".....", ".....", ".....", "....."], "limit": 5}"#;
+    let (status, reply) = service.handle("POST", "/complete", body, &dicts);
+    assert_eq!(status, 200);
+    assert_eq!(reply["squares"][0][4], "uvwxy");
+    assert_eq!(reply["timed_out"], false);
+
+    let body = r#"{"slot": "c0", "pattern": "a...."}"#;
+    let (_, reply) = service.handle("POST", "/candidates", body, &dicts);
+    assert_eq!(reply, json!({ "count": 2, "words": ["abcde", "afkpu"] }));
+    let body = r#"{"slot": "c0", "pattern": "a....", "limit": 1}"#;
+    let (_, reply) = service.handle("POST", "/candidates", body, &dicts);
+    assert_eq!(reply, json!({ "count": 2, "words": ["abcde"] }));
+
+    let body = r#"{"square": ["abcde", "fghij", "klmno", "pqrst", "uvwxz"]}"#;
+    let (_, reply) = service.handle("POST", "/verify", body, &dicts);
+    assert_eq!(reply["valid"], false);
+    assert_eq!(reply["misses"], json!(["row 4", "col 4"]));
+
+    assert_eq!(service.handle("GET", "/verify", "", &dicts).0, 405);
+    assert_eq!(service.handle("POST", "/nothing", "", &dicts).0, 404);
+    assert_eq!(service.handle("POST", "/verify", "{", &dicts).0, 400);
+    let body = r#"{"grid": [".....", ".....", ".....", ".....", "....."], "limit": 0}"#;
+    assert_eq!(service.handle("POST", "/complete", body, &dicts).0, 400);
+
+    // Requests with too long a line or too many headers.
+    let request = |text: String| {
+        let mut input = std::io::Cursor::new(text.into_bytes());
+        service.request(&mut input, &dicts).unwrap().0
+    };
+    let body = r#"{"square": ["abcde", "fghij", "klmno", "pqrst", "uvwxy"]}"#;
+    let post = |headers: &str| {
+        format!("POST /verify HTTP/1.1\r\n{headers}Content-Length: {}\r\n\r\n{body}", body.len())
+    };
+    assert_eq!(request(post("")), 200);
+    assert_eq!(request(post(&format!("X-Long: {}\r\n", "x".repeat(MAX_LINE)))), 431);
+    assert_eq!(request(post(&"X-Many: x\r\n".repeat(MAX_HEADERS))), 431);
+    assert_eq!(request(format!("GET /{} HTTP/1.1\r\n\r\n", "x".repeat(MAX_LINE))), 400);
+}
@@ -0,0 +1,243 @@
+/*!
+Splitting a search across processes. The first-row words
+are divided into `N` contiguous ranges, and each process
+searches one range with `--shard I/N`. Ranges are balanced
+by an estimate of the search under each first-row word:
+the product, over the columns, of the number of column
+words starting with the letter the row word puts there.
+The estimate depends only on the dictionaries, so every
+process computes the same ranges.
+
+`ws5 merge` puts the saved shards back together, refusing
+to combine results whose metadata does not match. Shards
+are taken in shard order, so that the merged squares are in
+the order one search finds them. Results that are not all
+known shards are sorted instead, so that the merge does not
+depend on the order they are given in.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::metadata::*;
+use crate::results::*;
+use crate::search::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Error, anyhow, bail};
+
+/// One part of a sharded search: part `index` (from 1) of
+/// `count`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+/// Shards are given as `I/N`.
+impl FromStr for Shard {
+    type Err = Error;
+
+    fn from_str(shard: &str) -> Result<Self, Error> {
+        let Some((index, count)) = shard.split_once('/') else {
+            bail!("{shard}: expected shard such as 2/8");
+        };
+        let (Ok(index), Ok(count)) = (index.parse(), count.parse()) else {
+            bail!("{shard}: expected shard such as 2/8");
+        };
+        if index == 0 || index > count {
+            bail!("{shard}: shard must be from 1 to {count}");
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Estimated size of the search under first-row word `w`.
+fn estimate(w: Word, dicts: &Dicts) -> f64 {
+    w.bits()
+        .enumerate()
+        .map(|(c, bits)| {
+            let start = Word::from_bits([bits, 0, 0, 0, 0]);
+            dicts.get(5 + c).match_count(start) as f64
+        })
+        .product()
+}
+
+impl Shard {
+    /// The range of first-row words, as indices into the
+    /// row 0 dictionary, that this shard searches.
+    pub fn range(&self, dicts: &Dicts) -> Range<usize> {
+        let mut weights: Vec<f64> = dicts
+            .get(0)
+            .into_iter()
+            .map(|&w| estimate(w, dicts))
+            .collect();
+        if weights.iter().sum::<f64>() == 0.0 {
+            weights.fill(1.0);
+        }
+        let total: f64 = weights.iter().sum();
+
+        // Each word goes to the shard its share of the
+        // running total starts in.
+        let mut before = 0.0;
+        let shards: Vec<usize> = weights
+            .iter()
+            .map(|w| {
+                let shard = ((before / total * self.count as f64) as usize).min(self.count - 1);
+                before += w;
+                shard
+            })
+            .collect();
+        let start = shards.partition_point(|&s| s < self.index - 1);
+        let end = shards.partition_point(|&s| s < self.index);
+        start..end
+    }
+}
+
+/// The shard that saved results come from, if their
+/// metadata says.
+fn shard_of(results: &Results) -> Option<Shard> {
+    let metadata = results.metadata.as_ref()?;
+    metadata.settings["shard"].as_str()?.parse().ok()
+}
+
+/// Combine saved results into one list of squares without
+/// duplicates. If every part is a shard, the parts are put
+/// in shard order, which is search order; otherwise the
+/// squares are sorted.
+pub fn merge_squares(mut saved: Vec<Results>) -> Vec<Square> {
+    let sharded = saved.iter().all(|r| shard_of(r).is_some());
+    if sharded {
+        saved.sort_by_key(|r| shard_of(r).map(|s| s.index));
+    }
+    let mut seen = HashSet::new();
+    let mut squares: Vec<Square> = saved
+        .into_iter()
+        .flat_map(|r| r.squares)
+        .filter(|s| seen.insert(s.clone()))
+        .collect();
+    if !sharded {
+        squares.sort();
+    }
+    squares
+}
+
+/// Arguments for the `merge` command.
+#[derive(clap::Args)]
+pub struct MergeArgs {
+    #[arg(required=true, help="saved squares to merge")]
+    pub squares: Vec<PathBuf>,
+    #[arg(short, long, help="output file, if not the main output file")]
+    pub output: Option<PathBuf>,
+}
+
+impl MergeArgs {
+    /// Run the `merge` command: combine saved squares into
+    /// one file without duplicates, as by [merge_squares()].
+    /// Saved metadata must match, and is combined.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let saved: Vec<Results> = self
+            .squares
+            .iter()
+            .map(|path| read_results(path, dicts))
+            .collect::<Result<_, _>>()?;
+
+        // Results with metadata must all match, and cannot
+        // be mixed with results without.
+        let parts: Vec<Metadata> = saved.iter().filter_map(|r| r.metadata.clone()).collect();
+        if !parts.is_empty()
+            && let Some(i) = saved.iter().position(|r| r.metadata.is_none())
+        {
+            bail!("{}: no metadata to check", self.squares[i].display());
+        }
+        for (path, metadata) in self.squares.iter().zip(&parts).skip(1) {
+            parts[0].check(metadata).map_err(|e| {
+                let first = self.squares[0].display();
+                anyhow!("{} does not match {first}: {e}", path.display())
+            })?;
+        }
+
+        let squares = merge_squares(saved);
+        // Keep near misses marked.
+        let near_misses = squares.iter().any(|s| !misses(s, dicts).is_empty());
+        let output: &Path = self.output.as_ref().unwrap_or(&args.output);
+        let metadata = (!parts.is_empty()).then(|| Metadata::merge(&parts));
+        write_squares(output, args.format, &squares, dicts, near_misses, metadata.as_ref())?;
+        summary(output, format!("{} squares", squares.len()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_shard() {
+    assert_eq!("2/8".parse::<Shard>().unwrap(), Shard { index: 2, count: 8 });
+    assert!("0/8".parse::<Shard>().is_err());
+    assert!("9/8".parse::<Shard>().is_err());
+    assert!("2".parse::<Shard>().is_err());
+    assert_eq!(Shard { index: 2, count: 8 }.to_string(), "2/8");
+
+    let text = std::fs::read_to_string("small-dict.txt").unwrap();
+    let (words, _) = crate::loader::load_words(&text);
+    let dicts = Dicts::from(Dict::from_words(&words));
+    let count = 3;
+    let ranges: Vec<Range<usize>> = (1..=count)
+        .map(|index| Shard { index, count }.range(&dicts))
+        .collect();
+    assert_eq!(ranges[0].start, 0);
+    assert_eq!(ranges[2].end, words.len());
+    assert!(ranges.windows(2).all(|r| r[0].end == r[1].start));
+
+    // The shards together find what one search does.
+    let mut whole = Vec::new();
+    AppState::default().find_completions(&Square::default(), &dicts, &mut whole);
+    let mut saved = Vec::new();
+    for (i, range) in ranges.into_iter().enumerate() {
+        let mut state = AppState { first_rows: Some(range), ..AppState::default() };
+        let mut squares = Vec::new();
+        state.find_completions(&Square::default(), &dicts, &mut squares);
+        let settings = serde_json::json!({ "shard": Shard { index: i + 1, count }.to_string() });
+        let metadata = Metadata {
+            version: "0".to_string(),
+            dict: FileInfo::new(Path::new("small-dict.txt")).unwrap(),
+            columns: None,
+            constraints: None,
+            template: None,
+            alphabet: None,
+            settings,
+            nodes: 0,
+            elapsed: 0.0,
+            finished: true,
+            stop: None,
+        };
+        saved.push(Results { squares, metadata: Some(metadata) });
+    }
+
+    // Merged in any order, they come out in search order.
+    let unmarked = |saved: &[Results]| -> Vec<Results> {
+        saved
+            .iter()
+            .map(|r| Results { squares: r.squares.clone(), metadata: None })
+            .collect()
+    };
+    let forward = unmarked(&saved);
+    saved.reverse();
+    let backward = unmarked(&saved);
+    assert_eq!(merge_squares(saved), whole);
+
+    // Without shard metadata, they come out sorted.
+    let mut sorted = whole;
+    sorted.sort();
+    assert_eq!(merge_squares(forward), sorted);
+    assert_eq!(merge_squares(backward), sorted);
+}
@@ -3,15 +3,18 @@
 // XXX The bit arithmetic here should be moved into [Word]
 // by refactoring.
 
+#[cfg(test)]
+use crate::alphabet::*;
 use crate::words::*;
 
 use std::collections::HashSet;
 
+use anyhow::{Error, bail};
 use serde::{self, ser::SerializeSeq};
 
 /// Save all the rows and columns of the grid separately for
 /// (dubious) efficiency reasons.
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Square([u32; 10]);
 
 impl Square {
@@ -44,6 +47,48 @@ impl Square {
         }
     }
 
+    /// Make a square from its rows.
+    pub fn from_words(rows: [Word; 5]) -> Self {
+        let mut s = Self::default();
+        for (i, row) in rows.into_iter().enumerate() {
+            s.set_pos(i, row);
+        }
+        s
+    }
+
+    /// Parse a square written as five lines of letters,
+    /// with `.` for blanks: the same form
+    /// [Square::as_string()] produces.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let rows: Vec<Word> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(Word::from_str)
+            .collect::<Result<_, _>>()?;
+        let Ok(rows) = rows.try_into() else {
+            bail!("square must have five rows");
+        };
+        Ok(Self::from_words(rows))
+    }
+
+    /// Get the bitfield of the cell at the given row and
+    /// column.
+    pub fn cell(&self, row: usize, col: usize) -> u8 {
+        self.get_pos(row).get_bits(col)
+    }
+
+    /// Set the bitfield of the cell at the given row and
+    /// column, in both its row and column words.
+    pub fn set_cell(&mut self, row: usize, col: usize, bits: u8) {
+        let set = |target: &mut u32, offset: usize| {
+            let shift = 6 * (4 - offset);
+            *target = (*target & !(0x3f << shift)) | (bits as u32) << shift;
+        };
+        set(&mut self.0[row], col);
+        set(&mut self.0[col + 5], row);
+    }
+
     /// Make a printable version of the grid. Does not end
     /// with a newline.
     // XXX Should this be a Display impl?
@@ -62,6 +107,11 @@ impl Square {
         result
     }
 
+    /// Check whether this word square is entirely blank.
+    pub fn is_empty(&self) -> bool {
+        (0..5).all(|p| self.get_pos(p).is_empty())
+    }
+
     /// Check whether this word square is fully filled out.
     // XXX This used to be a complicated `for` loop.
     pub fn is_full(&self) -> bool {
@@ -88,6 +138,12 @@ impl Square {
         let down = self.get_pos(5);
         across.is_transposed(down)
     }
+
+    /// A magic square is its own transpose: each column is
+    /// the same word as the row of the same number.
+    pub fn is_magic(&self) -> bool {
+        (0..5).all(|i| self.get_pos(i) == self.get_pos(i + 5))
+    }
 }
 
 #[cfg(test)]
@@ -122,6 +178,18 @@ impl Square {
         s
     }
 
+    /// The square used in tests, whose rows `abcde` to
+    /// `uvwxy` and columns `afkpu` to `ejoty` are ten
+    /// different words.
+    pub fn test() -> Self {
+        Self::from_rows(["abcde", "fghij", "klmno", "pqrst", "uvwxy"])
+    }
+
+    /// The words at each position, rows then columns.
+    pub fn words(&self) -> Vec<Word> {
+        (0..10).map(|p| self.get_pos(p)).collect()
+    }
+
     /// Get the bit representation of a particular position in the
     /// square.
     fn get_coord(&self, pos: usize, offset: usize) -> Option<u8> {
@@ -136,39 +204,26 @@ impl Square {
     }
 
     /// Set a particular position in the square in tests.
-    pub fn set_coord(&mut self, pos: usize, offset: usize, value: char) {
+    pub fn set_coord(&mut self, pos: usize, offset: usize, value: &str) {
         assert!(pos < 10 && offset < 5);
 
-        let bit_v = if value.is_ascii_alphabetic() {
-            0x20 | (value as u8 - b'a') as u32
-        } else if value == '.' {
-            0
-        } else {
-            panic!("set_coord: bad value");
+        let bits = match Alphabet::current().bits(value).as_deref() {
+            Some(&[b]) => b,
+            _ => panic!("set_coord: bad value"),
         };
 
-        let mask = !(0x3f << (6 * (4 - offset)));
-        let v = bit_v << (6 * (4 - offset));
-        let target = &mut self.0[pos];
-        *target = (*target & mask) | v;
-
-        let (pos, offset) = if pos < 5 {
-            (offset + 5, pos)
+        if pos < 5 {
+            self.set_cell(pos, offset, bits);
         } else {
-            (offset, pos - 5)
-        };
-
-        let mask = !(0x3f << (6 * (4 - offset)));
-        let v = bit_v << (6 * (4 - offset));
-        let target = &mut self.0[pos];
-        *target = (*target & mask) | v;
+            self.set_cell(offset, pos - 5, bits);
+        }
     }
 
     /// Get a particular coordinate in tests.
-    pub fn get_char(&self, pos: usize, offset: usize) -> char {
+    pub fn get_char(&self, pos: usize, offset: usize) -> &'static str {
         match self.get_coord(pos, offset) {
-            Some(v) => (v + b'a') as char,
-            None => '.',
+            Some(v) => Alphabet::current().letter(v),
+            None => ".",
         }
     }
 
@@ -181,7 +236,7 @@ impl Square {
     /// Test validity of the current position.
     fn fsck_square(&self) {
         for p in 0..10 {
-            let word: Vec<char> = self.get_pos(p).chars().collect();
+            let word: Vec<&str> = self.get_pos(p).chars().collect();
             let (i, range) = if p < 5 {
                 (p, 5..10)
             } else {
@@ -206,9 +261,9 @@ fn test_coord_pos() {
     let mut s = Square::default();
     let wx = Word::from_str("abcde").unwrap();
     s.set_pos(1, wx);
-    assert_eq!(s.get_char(1, 1), 'b');
-    assert_eq!(s.get_char(6, 0), '.');
-    assert_eq!(s.get_char(8, 1), 'd');
+    assert_eq!(s.get_char(1, 1), "b");
+    assert_eq!(s.get_char(6, 0), ".");
+    assert_eq!(s.get_char(8, 1), "d");
 
     let wy = Word::from_str("udwxy").unwrap();
     assert!(s.is_fit(8, wy));
@@ -225,7 +280,7 @@ fn test_coord_pos() {
             if j == 3 {
                 continue;
             }
-            assert_eq!(s.get_char(i, j), '.', "{i} {j}");
+            assert_eq!(s.get_char(i, j), ".", "{i} {j}");
         }
     }
     for i in 5..10 {
@@ -236,7 +291,7 @@ fn test_coord_pos() {
             if j == 1 {
                 continue;
             }
-            assert_eq!(s.get_char(i, j), '.', "{i} {j}");
+            assert_eq!(s.get_char(i, j), ".", "{i} {j}");
         }
     }
 }
@@ -255,6 +310,32 @@ impl serde::Serialize for Square {
     }
 }
 
+/// Name a position of the square for humans: `row 2`,
+/// `col 3`.
+pub fn pos_name(pos: usize) -> String {
+    if pos < 5 {
+        format!("row {pos}")
+    } else {
+        format!("col {}", pos - 5)
+    }
+}
+
+/// A square together with the positions in it that are not
+/// words, for saving near misses.
+#[derive(serde::Serialize)]
+pub struct NearMiss<'a> {
+    square: &'a Square,
+    misses: Vec<String>,
+}
+
+impl<'a> NearMiss<'a> {
+    /// Mark the given positions of the square as misses.
+    pub fn new(square: &'a Square, misses: &[usize]) -> Self {
+        let misses = misses.iter().map(|&p| pos_name(p)).collect();
+        Self { square, misses }
+    }
+}
+
 #[test]
 fn test_as_string() {
     let mut s = Square::default();
@@ -297,3 +378,16 @@ fn test_set_pos() {
     ]);
     assert_eq!(s, t, "\n{}\n\n{}", s.as_string(), t.as_string());
 }
+
+#[test]
+fn test_parse() {
+    let s = Square::parse("ab...\n.....\n\n..c..\n.....\n....d\n").unwrap();
+    assert_eq!(s.as_string(), "ab...\n.....\n..c..\n.....\n....d");
+    assert_eq!(s.get_char(6, 0), "b");
+    assert_eq!(s.get_char(9, 4), "d");
+    assert!(!s.is_empty());
+    assert!(Square::default().is_empty());
+
+    assert!(Square::parse("abcde\n").is_err());
+    assert!(Square::parse("ab\n.....\n.....\n.....\n.....\n").is_err());
+}
@@ -0,0 +1,278 @@
+/*!
+Word participation statistics over a set of squares, for
+dictionary curation: how many squares each word appears in
+and at which positions, how often each letter appears at
+each place in the words used, which dictionary words never
+appear in any square, and which words are the most
+productive.
+
+Reports are plain text, or CSV or JSON chosen by the
+extension of the file they are saved to. The CSV report
+has one line per dictionary word.
+*/
+
+use crate::alphabet::*;
+use crate::appstate::*;
+use crate::dict::*;
+use crate::results::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use serde_json::json;
+
+/// Default number of most productive words to show.
+pub const STATS_TOP: usize = 20;
+
+/// Most unused words to list in a text report.
+const UNUSED_SHOWN: usize = 50;
+
+/// Arguments for the `stats` command.
+#[derive(clap::Args)]
+pub struct StatsArgs {
+    #[arg(help="saved squares to report on")]
+    pub squares: PathBuf,
+    #[arg(short, long, help="save the report to this file: .csv, .json or text")]
+    pub output: Option<PathBuf>,
+    #[arg(long, default_value_t=STATS_TOP, help="number of most productive words to show")]
+    pub top: usize,
+}
+
+/// Word and letter counts over a set of squares.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Number of squares counted.
+    pub squares: usize,
+    /// For each word used, the number of squares it appears
+    /// in.
+    pub counts: HashMap<Word, usize>,
+    /// For each word used, the number of squares it appears
+    /// in at each position.
+    pub positions: HashMap<Word, [usize; 10]>,
+    /// For each place in a word, the number of words used
+    /// with each letter there.
+    pub letters: [[usize; MAX_LETTERS]; 5],
+}
+
+impl Stats {
+    /// Count the words of a square.
+    pub fn add(&mut self, s: &Square) {
+        self.squares += 1;
+        // A doubled word counts once per square.
+        let words: HashSet<Word> = (0..10).map(|p| s.get_pos(p)).collect();
+        for word in words {
+            *self.counts.entry(word).or_default() += 1;
+        }
+        for p in 0..10 {
+            let word = s.get_pos(p);
+            self.positions.entry(word).or_default()[p] += 1;
+            for (i, b) in word.bits().enumerate() {
+                self.letters[i][(b & 0x1f) as usize] += 1;
+            }
+        }
+    }
+
+    /// Number of squares `word` appears in.
+    pub fn count(&self, word: Word) -> usize {
+        self.counts.get(&word).copied().unwrap_or(0)
+    }
+
+    /// Words used, most productive first.
+    pub fn productive(&self) -> Vec<(Word, usize)> {
+        let mut words: Vec<(Word, usize)> = self
+            .positions
+            .keys()
+            .map(|&w| (w, self.count(w)))
+            .collect();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        words
+    }
+
+    /// Dictionary words that appear in no square, in
+    /// order.
+    pub fn unused(&self, dicts: &Dicts) -> Vec<Word> {
+        dict_words(dicts)
+            .into_iter()
+            .filter(|w| !self.positions.contains_key(w))
+            .collect()
+    }
+
+    /// Describe the positions a word was used at, such as
+    /// `row 0 3, col 2 1`.
+    fn describe(&self, word: Word) -> String {
+        let counts = self.positions.get(&word).copied().unwrap_or_default();
+        let used: Vec<String> = (0..10)
+            .filter(|&p| counts[p] > 0)
+            .map(|p| format!("{} {}", pos_name(p), counts[p]))
+            .collect();
+        used.join(", ")
+    }
+
+    /// Letters that appear in any used word, in alphabet
+    /// order.
+    fn used_letters(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_LETTERS).filter(|&c| self.letters.iter().any(|counts| counts[c] > 0))
+    }
+
+    /// A plain text report.
+    pub fn text(&self, dicts: &Dicts, top: usize) -> String {
+        let alphabet = Alphabet::current();
+        let mut out = String::new();
+        let unused = self.unused(dicts);
+        let nwords = dict_words(dicts).len();
+        writeln!(out, "{} squares", self.squares).unwrap();
+        writeln!(
+            out,
+            "{} of {} dictionary words used, {} unused",
+            self.positions.len(),
+            nwords,
+            unused.len(),
+        ).unwrap();
+
+        writeln!(out, "\nmost productive words:").unwrap();
+        for (word, count) in self.productive().into_iter().take(top) {
+            writeln!(out, "  {word} {count}: {}", self.describe(word)).unwrap();
+        }
+
+        writeln!(out, "\nletters by place in word:").unwrap();
+        for c in self.used_letters() {
+            let counts: Vec<String> = self
+                .letters
+                .iter()
+                .map(|counts| format!("{:7}", counts[c]))
+                .collect();
+            writeln!(out, "  {:2} {}", alphabet.letter(c as u8), counts.join("")).unwrap();
+        }
+
+        if !unused.is_empty() {
+            writeln!(out, "\nunused words:").unwrap();
+            let shown: Vec<String> = unused
+                .iter()
+                .take(UNUSED_SHOWN)
+                .map(|w| w.as_string())
+                .collect();
+            write!(out, "  {}", shown.join(" ")).unwrap();
+            if unused.len() > UNUSED_SHOWN {
+                write!(out, " ... ({} more)", unused.len() - UNUSED_SHOWN).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        out
+    }
+
+    /// A CSV report: each dictionary word with its square
+    /// count and its count at each position.
+    pub fn csv(&self, dicts: &Dicts) -> String {
+        let mut out = String::new();
+        let names: Vec<String> = (0..10).map(|p| pos_name(p).replace(' ', "")).collect();
+        writeln!(out, "word,squares,{}", names.join(",")).unwrap();
+        for word in dict_words(dicts) {
+            let counts = self.positions.get(&word).copied().unwrap_or_default();
+            let counts: Vec<String> = counts.iter().map(|n| n.to_string()).collect();
+            writeln!(out, "{word},{},{}", self.count(word), counts.join(",")).unwrap();
+        }
+        out
+    }
+
+    /// A JSON report.
+    pub fn json(&self, dicts: &Dicts) -> serde_json::Value {
+        let alphabet = Alphabet::current();
+        let words: Vec<serde_json::Value> = self
+            .productive()
+            .into_iter()
+            .map(|(word, count)| {
+                let counts = self.positions[&word];
+                let positions: serde_json::Map<String, serde_json::Value> = (0..10)
+                    .filter(|&p| counts[p] > 0)
+                    .map(|p| (pos_name(p), json!(counts[p])))
+                    .collect();
+                json!({
+                    "word": word.as_string(),
+                    "squares": count,
+                    "positions": positions,
+                })
+            })
+            .collect();
+        let letters: serde_json::Map<String, serde_json::Value> = self
+            .used_letters()
+            .map(|c| {
+                let counts: Vec<usize> = self.letters.iter().map(|counts| counts[c]).collect();
+                (alphabet.letter(c as u8).to_string(), json!(counts))
+            })
+            .collect();
+        let unused: Vec<String> = self.unused(dicts).iter().map(|w| w.as_string()).collect();
+        json!({
+            "squares": self.squares,
+            "words": words,
+            "letters": letters,
+            "unused": unused,
+        })
+    }
+
+    /// Save a report, in the format given by the file's
+    /// extension.
+    pub fn save(&self, path: &Path, dicts: &Dicts, top: usize) -> Result<(), Error> {
+        let report = match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => self.csv(dicts),
+            Some("json") => serde_json::to_string_pretty(&self.json(dicts))? + "\n",
+            _ => self.text(dicts, top),
+        };
+        std::fs::write(path, report)?;
+        Ok(())
+    }
+}
+
+/// Every word in the dictionaries, in order.
+fn dict_words(dicts: &Dicts) -> BTreeSet<Word> {
+    (0..10).flat_map(|p| dicts.get(p)).copied().collect()
+}
+
+impl StatsArgs {
+    /// Run the `stats` command: report on saved squares.
+    pub fn run(&self, _args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        let mut stats = Stats::default();
+        for s in read_squares(&self.squares, dicts)? {
+            stats.add(&s);
+        }
+        match self.output {
+            Some(ref path) => stats.save(path, dicts, self.top),
+            None => {
+                print!("{}", stats.text(dicts, self.top));
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_stats() {
+    let square = Square::test();
+    let unused = Word::from_str("zzzzz").unwrap();
+    let dicts = Dicts::test(&["zzzzz"]);
+
+    let mut stats = Stats::default();
+    stats.add(&square);
+    stats.add(&square);
+    let abcde = Word::from_str("abcde").unwrap();
+    assert_eq!(stats.count(abcde), 2);
+    assert_eq!(stats.positions[&abcde], [2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(stats.unused(&dicts), [unused]);
+    assert_eq!(stats.productive()[0], (abcde, 2));
+    // `a` starts two words in each square.
+    let a = Word::from_str("a....").unwrap().get_bits(0) & 0x1f;
+    assert_eq!(stats.letters[0][a as usize], 4);
+
+    let csv = stats.csv(&dicts);
+    assert!(csv.starts_with("word,squares,row0,"));
+    assert!(csv.contains("\nabcde,2,2,0,0,0,0,0,0,0,0,0\n"));
+    assert!(csv.ends_with("zzzzz,0,0,0,0,0,0,0,0,0,0,0\n"));
+
+    let json = stats.json(&dicts);
+    assert_eq!(json["squares"], 2);
+    assert_eq!(json["words"][0]["positions"]["row 0"], 2);
+    assert_eq!(json["unused"][0], "zzzzz");
+}
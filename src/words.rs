@@ -6,7 +6,8 @@ is in the most-significant bits. Each field is of the form
     sccccc
 
 where `s` is 1 if the field is filled, in which case the
-remaining five `ccccc` bits are an encoding of the letter:
+remaining five `ccccc` bits are the code of the letter in
+the current [Alphabet]: for the default alphabet
 `'a' == 0`, `'b' == 1`, etc. If `s` is 0, the field is
 empty, and the remaining `ccccc` bits must be 0.
 */
@@ -14,6 +15,7 @@ empty, and the remaining `ccccc` bits must be 0.
 // XXX The bit arithmetic done here should mostly be factored: it's
 // error-prone and hard to read.
 
+use crate::alphabet::*;
 use crate::dict::*;
 
 use anyhow::{Error, bail};
@@ -30,26 +32,25 @@ impl Word {
     /// Turn the given string into a [Word], failing
     /// if it does not meet necessary conditions.
     pub fn from_str(word: &str) -> Result<Self, Error> {
+        // Must be letters of the alphabet, or `.`.
+        let Some(bits) = Alphabet::current().bits(word) else {
+            bail!("invalid char error");
+        };
+
         // Must be right length.
-        if word.len() != 5 {
+        let Ok(bits) = bits.try_into() else {
             bail!("word length error");
-        }
+        };
 
-        // Must be an ASCII lowercase letter, or `.`.  If
-        // so, fill the bitfields.
-        let mut result = 0u32;
-        for (i, c) in word.chars().enumerate() {
-            if c == '.' {
-                continue;
-            }
-            if !c.is_ascii_lowercase() {
-                bail!("invalid char error");
-            }
-            let c = c as u8 - b'a';
-            result |= (0x20 | c as u32) << (6 * (4 - i));
-        }
+        Ok(Self::from_bits(bits))
+    }
 
-        Ok(Self(result))
+    /// Make a [Word] from its bitfields.
+    pub fn from_bits(bits: [u8; 5]) -> Self {
+        let result = bits
+            .iter()
+            .fold(0u32, |w, &b| (w << 6) | b as u32);
+        Self(result)
     }
 
     /// Iterator over the bitfields of the word.
@@ -75,11 +76,12 @@ impl Word {
     // XXX Why is this here instead of there?
     pub fn build_word_index(words: &[Word]) -> WordIndex {
         let mut words = words.to_vec();
+        let nletters = Alphabet::current().len();
         // `std::array::from_fn()` is new and useful.
         // `std::slice::partition_point()` is old and useful.
         array_fn(|i| {
             words.sort_by_key(|&w| (w.get_bits(i), w));
-            array_fn(|j| {
+            (0..nletters).map(|j| {
                 let start = words.partition_point(|w| {
                     w.get_bits(i) < 0x20 | j as u8
                 });
@@ -87,17 +89,19 @@ impl Word {
                     w.get_bits(i) <= 0x20 | j as u8
                 });
                 words[start..end].to_vec()
-            })
+            }).collect()
         })
     }
 
-    /// Iterator over the chars of a word.
-    pub fn chars(self) -> impl Iterator<Item = char> {
+    /// Iterator over the letters of a word, as spelled in
+    /// the current [Alphabet].
+    pub fn chars(self) -> impl Iterator<Item = &'static str> {
+        let alphabet = Alphabet::current();
         self.bits().map(|bits| {
             if bits & 0x20 > 0 {
-                ((bits & 0x1f) + b'a') as char
+                alphabet.letter(bits & 0x1f)
             } else if bits == 0 {
-                '.'
+                "."
             } else {
                 panic!("internal error: bad bits value");
             }
@@ -121,6 +125,14 @@ impl Word {
         true
     }
 
+    /// True if every field is blank or a letter of the
+    /// current [Alphabet], and the unused high bits are 0.
+    pub fn is_valid(self) -> bool {
+        let nletters = Alphabet::current().len();
+        self.0 >> 30 == 0
+            && self.bits().all(|b| b == 0 || (b & 0x20 > 0 && ((b & 0x1f) as usize) < nletters))
+    }
+
     /// True if the word contains all blanks.
     pub fn is_empty(self) -> bool {
         self.0 == 0
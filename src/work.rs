@@ -0,0 +1,649 @@
+/*!
+Dynamic load balancing over a local socket. A coordinator,
+`ws5 serve-work`, hands out units of work to `ws5 worker`
+processes as they ask for it, and gathers the squares and
+node counts they send back. A unit is one first-row word:
+the worker searches the completions of a template with that
+word in row 0. If a worker goes away in the middle of a
+unit, the unit goes back in the queue for another worker.
+
+Messages are JSON objects, one per line. A worker starts
+with `{"type":"ready","metadata":{…}}`, and answers each
+`{"type":"work","id":…,"word":…}` with
+`{"type":"result","id":…,"squares":[…],"nodes":…}` until it
+is sent `{"type":"done"}`. Workers must be run with the same
+dictionary and search options as the coordinator: the
+metadata of the ready message gives the hashes of the
+worker's files and its settings, and a worker that differs
+is sent `{"type":"rejected","reason":…}`. So is a worker
+that sends a result before it is ready, or for a unit it
+was not given.
+
+A solution limit applies to the whole run: the coordinator
+stops handing out units once the units finished in order
+have that many squares. A worker that takes longer than the
+unit timeout to report is dropped, and its unit goes back in
+the queue.
+
+Addresses are `host:port` for TCP, or `unix:PATH` for a
+Unix socket.
+*/
+
+use crate::appstate::*;
+use crate::dict::*;
+use crate::metadata::*;
+use crate::results::*;
+use crate::squares::*;
+use crate::words::*;
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, anyhow, bail};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+/// Default coordinator address.
+const ADDRESS: &str = "127.0.0.1:7878";
+
+/// How long to wait after a failed accept before trying
+/// again.
+const ACCEPT_RETRY: Duration = Duration::from_secs(1);
+
+/// How often to check for a worker connecting.
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// Where a coordinator listens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+/// Addresses are `host:port` or `unix:PATH`.
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(address: &str) -> Result<Self, Error> {
+        match address.strip_prefix("unix:") {
+            Some("") => bail!("{address}: missing socket path"),
+            Some(path) => Ok(Address::Unix(PathBuf::from(path))),
+            None => Ok(Address::Tcp(address.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{address}"),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A connection, split for reading and writing.
+pub type Connection = (BufReader<Box<dyn Read + Send>>, Box<dyn Write + Send>);
+
+/// Split a stream into a [Connection].
+fn split<S>(stream: S, clone: S) -> Connection
+where
+    S: Read + Write + Send + 'static,
+{
+    (BufReader::new(Box::new(stream)), Box::new(clone))
+}
+
+/// A coordinator's listening socket.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Take a worker that has connected, if any, and give
+    /// up on reading from it after `timeout`. The listener
+    /// does not block, so that it can be stopped.
+    fn accept(&self, timeout: Duration) -> Result<Option<Connection>, Error> {
+        let accepted = match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(timeout))?;
+                let clone = stream.try_clone()?;
+                Ok(split(stream, clone))
+            }),
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(timeout))?;
+                let clone = stream.try_clone()?;
+                Ok(split(stream, clone))
+            }),
+        };
+        match accepted {
+            Ok(connection) => connection.map(Some),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Address {
+    /// Listen for workers. A Unix socket left over from an
+    /// earlier coordinator is replaced.
+    pub fn listen(&self) -> Result<Listener, Error> {
+        match self {
+            Address::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(listener))
+            }
+            Address::Unix(path) => {
+                if let Ok(meta) = std::fs::symlink_metadata(path)
+                    && meta.file_type().is_socket()
+                {
+                    std::fs::remove_file(path)?;
+                }
+                let listener = UnixListener::bind(path)?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Unix(listener))
+            }
+        }
+    }
+
+    /// Connect to a coordinator.
+    pub fn connect(&self) -> Result<Connection, Error> {
+        match self {
+            Address::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                let clone = stream.try_clone()?;
+                Ok(split(stream, clone))
+            }
+            Address::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                let clone = stream.try_clone()?;
+                Ok(split(stream, clone))
+            }
+        }
+    }
+}
+
+/// Messages from the coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Assignment {
+    Work { id: usize, word: String },
+    Done,
+    Rejected { reason: String },
+}
+
+/// Messages from a worker.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Report {
+    Ready { metadata: Box<Metadata> },
+    Result { id: usize, squares: Vec<Value>, nodes: usize },
+}
+
+/// Send a message as a line of JSON.
+fn send<T: Serialize>(output: &mut dyn Write, message: &T) -> Result<(), Error> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    output.write_all(line.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Receive a message, or [None] if the other end has gone.
+fn receive<T: DeserializeOwned>(input: &mut impl BufRead) -> Result<Option<T>, Error> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+/// The coordinator's record of the work.
+#[derive(Default)]
+struct Queue {
+    /// Units not yet handed out.
+    pending: VecDeque<usize>,
+    /// Units handed out and not yet finished.
+    outstanding: HashSet<usize>,
+    /// Squares found for each finished unit.
+    results: BTreeMap<usize, Vec<Square>>,
+    /// Nodes searched for finished units.
+    nodes: usize,
+    /// Workers connected.
+    workers: usize,
+    /// Most squares wanted, if any limit.
+    limit: Option<usize>,
+}
+
+impl Queue {
+    /// True if the units finished in order, from the first,
+    /// have found as many squares as the limit.
+    fn enough(&self) -> bool {
+        let Some(limit) = self.limit else {
+            return false;
+        };
+        let mut found = 0;
+        for (i, (&id, squares)) in self.results.iter().enumerate() {
+            if id != i {
+                break;
+            }
+            found += squares.len();
+            if found >= limit {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn finished(&self) -> bool {
+        self.enough() || (self.pending.is_empty() && self.outstanding.is_empty())
+    }
+}
+
+/// A run for workers to search.
+pub struct Plan {
+    /// The first-row words, one per unit.
+    pub words: Vec<String>,
+    /// The coordinator's files and settings, which workers
+    /// must match.
+    pub metadata: Metadata,
+    /// Most squares wanted from the whole run.
+    pub limit: Option<usize>,
+    /// Longest to wait for a worker to report.
+    pub unit_timeout: Duration,
+}
+
+/// Work shared by the threads serving workers.
+struct Coordinator {
+    words: Vec<String>,
+    metadata: Metadata,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    /// Set when the run is over, to stop accepting workers.
+    stopping: AtomicBool,
+}
+
+impl Coordinator {
+    /// Serve one worker until it leaves or the work is
+    /// done, putting back any unit it leaves unfinished.
+    /// The worker was counted when it connected.
+    fn serve(&self, (mut input, mut output): Connection) {
+        let mut current = None;
+        if let Err(e) = self.assign(&mut input, &mut output, &mut current) {
+            eprintln!("ws5: worker: {e}");
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(id) = current
+            && queue.outstanding.remove(&id)
+        {
+            queue.pending.push_front(id);
+        }
+        queue.workers -= 1;
+        self.changed.notify_all();
+    }
+
+    /// Take reports from a worker and hand it units,
+    /// keeping track of its `current` unit. A worker must
+    /// say it is ready, once, before it is given any work,
+    /// and may only report on the unit it was given.
+    fn assign(
+        &self,
+        input: &mut impl BufRead,
+        output: &mut dyn Write,
+        current: &mut Option<usize>,
+    ) -> Result<(), Error> {
+        let mut ready = false;
+        loop {
+            let Some(report) = receive::<Report>(input)? else {
+                return Ok(());
+            };
+            let checked = match report {
+                Report::Ready { ref metadata } if !ready => self.check(metadata),
+                Report::Ready { .. } => Err(anyhow!("ready more than once")),
+                Report::Result { .. } if !ready => Err(anyhow!("result before ready")),
+                Report::Result { id, .. } if *current != Some(id) => {
+                    Err(anyhow!("result for unit {id}, which it was not given"))
+                }
+                Report::Result { .. } => Ok(()),
+            };
+            if let Err(e) = checked {
+                let reason = e.to_string();
+                send(output, &Assignment::Rejected { reason: reason.clone() })?;
+                bail!("rejected: {reason}");
+            }
+            ready = true;
+            if let Report::Result { id, squares, nodes } = report {
+                let squares = squares
+                    .iter()
+                    .map(square_from_json)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut queue = self.queue.lock().unwrap();
+                if queue.outstanding.remove(&id) {
+                    queue.results.insert(id, squares);
+                    queue.nodes += nodes;
+                }
+                *current = None;
+                self.changed.notify_all();
+            }
+
+            // Wait for a unit to hand out, or for the end.
+            let next = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if queue.enough() {
+                        break None;
+                    }
+                    if let Some(id) = queue.pending.pop_front() {
+                        queue.outstanding.insert(id);
+                        break Some(id);
+                    }
+                    if queue.finished() {
+                        break None;
+                    }
+                    queue = self.changed.wait(queue).unwrap();
+                }
+            };
+            match next {
+                Some(id) => {
+                    *current = Some(id);
+                    let word = self.words[id].clone();
+                    send(output, &Assignment::Work { id, word })?;
+                }
+                None => {
+                    send(output, &Assignment::Done)?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl Coordinator {
+    /// Fail unless a worker with `metadata` searches as
+    /// this coordinator would.
+    fn check(&self, metadata: &Metadata) -> Result<(), Error> {
+        self.metadata.check(metadata)?;
+        let (mine, theirs) = (&self.metadata.settings["limit"], &metadata.settings["limit"]);
+        if mine != theirs {
+            bail!("different limit setting: {mine} and {theirs}");
+        }
+        Ok(())
+    }
+}
+
+/// Hand out the first-row words of `plan` to workers
+/// connecting to `listener` until every one has been
+/// searched, or the limit is reached. Returns the squares
+/// found, in first-row word order, the total nodes
+/// searched, and why the run stopped early, if it did.
+pub fn coordinate(listener: Listener, plan: Plan) -> (Vec<Square>, usize, Option<Stop>) {
+    let queue = Queue {
+        pending: (0..plan.words.len()).collect(),
+        limit: plan.limit,
+        ..Queue::default()
+    };
+    let coordinator = Arc::new(Coordinator {
+        words: plan.words,
+        metadata: plan.metadata,
+        queue: Mutex::new(queue),
+        changed: Condvar::new(),
+        stopping: AtomicBool::new(false),
+    });
+
+    // Accept workers in the background until the run is
+    // over, pausing after an error rather than spinning on
+    // it.
+    let server = Arc::clone(&coordinator);
+    let timeout = plan.unit_timeout;
+    let acceptor = std::thread::spawn(move || {
+        while !server.stopping.load(Ordering::Relaxed) {
+            match listener.accept(timeout) {
+                Ok(Some(connection)) => {
+                    server.queue.lock().unwrap().workers += 1;
+                    let server = Arc::clone(&server);
+                    std::thread::spawn(move || server.serve(connection));
+                }
+                Ok(None) => std::thread::sleep(ACCEPT_POLL),
+                Err(e) => {
+                    eprintln!("ws5: {e}");
+                    std::thread::sleep(ACCEPT_RETRY);
+                }
+            }
+        }
+    });
+
+    // Wait for the work to be done, and for the workers to
+    // be told so.
+    let (squares, nodes, stop) = {
+        let mut queue = coordinator.queue.lock().unwrap();
+        while !queue.finished() || queue.workers > 0 {
+            queue = coordinator.changed.wait(queue).unwrap();
+        }
+        let stop = queue.enough().then_some(Stop::Limit);
+        let limit = queue.limit.unwrap_or(usize::MAX);
+        let squares = queue.results.values().flatten().take(limit).cloned().collect();
+        (squares, queue.nodes, stop)
+    };
+
+    // Stop accepting, which also closes the listener.
+    coordinator.stopping.store(true, Ordering::Relaxed);
+    acceptor.join().unwrap();
+    (squares, nodes, stop)
+}
+
+/// Work for a coordinator until it is done, making a fresh
+/// search state for each unit with `state`. The worker's
+/// files and settings are given by `metadata`. Returns the
+/// number of units searched.
+pub fn work<F>(
+    (mut input, mut output): Connection,
+    dicts: &Dicts,
+    metadata: Metadata,
+    state: F,
+) -> Result<usize, Error>
+where
+    F: Fn() -> Result<AppState, Error>,
+{
+    let mut units = 0;
+    send(&mut output, &Report::Ready { metadata: Box::new(metadata) })?;
+    loop {
+        let Some(assignment) = receive(&mut input)? else {
+            bail!("coordinator went away");
+        };
+        let (id, word) = match assignment {
+            Assignment::Work { id, word } => (id, word),
+            Assignment::Done => return Ok(units),
+            Assignment::Rejected { reason } => bail!("rejected by the coordinator: {reason}"),
+        };
+
+        let mut template = Square::default();
+        template.set_pos(0, Word::from_str(&word)?);
+        let mut state = state()?;
+        let mut results = Vec::new();
+        state.find_completions(&template, dicts, &mut results);
+
+        let squares = results
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<_, _>>()?;
+        send(&mut output, &Report::Result { id, squares, nodes: state.nodes })?;
+        units += 1;
+    }
+}
+
+/// Arguments for the `serve-work` command.
+#[derive(clap::Args)]
+pub struct ServeWorkArgs {
+    #[arg(long, default_value=ADDRESS, help="address to listen on: host:port or unix:PATH")]
+    pub listen: Address,
+    #[arg(short, long, help="output file, if not the main output file")]
+    pub output: Option<PathBuf>,
+    #[arg(
+        long,
+        value_parser=parse_duration,
+        default_value="10m",
+        help="drop a worker that takes longer than this to report, such as 30s",
+    )]
+    pub unit_timeout: Duration,
+}
+
+impl ServeWorkArgs {
+    /// Run the `serve-work` command: coordinate workers
+    /// and save what they find.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        if args.template.is_some() || args.shard.is_some() {
+            bail!("serve-work takes neither a template nor a shard");
+        }
+        if args.timeout.is_some() || args.max_nodes.is_some() {
+            bail!("serve-work takes neither --timeout nor --max-nodes");
+        }
+        let words: Vec<String> = dicts.get(0).into_iter().map(|w| w.as_string()).collect();
+        let listener = self.listen.listen()?;
+        eprintln!("serving {} units on {}", words.len(), self.listen);
+        let start = Instant::now();
+        let plan = Plan {
+            words,
+            metadata: Metadata::new(args, 0, Duration::ZERO, None)?,
+            limit: args.limit,
+            unit_timeout: self.unit_timeout,
+        };
+        let (squares, nodes, stop) = coordinate(listener, plan);
+        if let Address::Unix(ref path) = self.listen {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let output: &Path = self.output.as_ref().unwrap_or(&args.output);
+        let metadata = Metadata::for_run(args, nodes, start.elapsed(), stop)?;
+        write_squares(output, args.format, &squares, dicts, args.max_misses > 0, metadata.as_ref())?;
+        summary(output, format!("{} squares ({nodes} nodes)", squares.len()));
+        Ok(())
+    }
+}
+
+/// Arguments for the `worker` command.
+#[derive(clap::Args)]
+pub struct WorkerArgs {
+    #[arg(long, default_value=ADDRESS, help="coordinator address: host:port or unix:PATH")]
+    pub connect: Address,
+}
+
+impl WorkerArgs {
+    /// Run the `worker` command: search units for a
+    /// coordinator until it is done.
+    pub fn run(&self, args: &Args, dicts: &Dicts) -> Result<(), Error> {
+        // These would cut units short without the
+        // coordinator knowing.
+        if args.timeout.is_some() || args.max_nodes.is_some() {
+            bail!("worker takes neither --timeout nor --max-nodes");
+        }
+        let metadata = Metadata::new(args, 0, Duration::ZERO, None)?;
+        let connection = self.connect.connect()?;
+        let units = work(connection, dicts, metadata, || AppState::for_search(args))?;
+        println!("{units} units");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_work() {
+    let text = std::fs::read_to_string("small-dict.txt").unwrap();
+    let (words, _) = crate::loader::load_words(&text);
+    let dicts = Dicts::from(Dict::from_words(&words));
+    let mut whole = Vec::new();
+    AppState::default().find_completions(&Square::default(), &dicts, &mut whole);
+
+    let metadata = Metadata {
+        version: "0".to_string(),
+        dict: FileInfo::new(Path::new("small-dict.txt")).unwrap(),
+        columns: None,
+        constraints: None,
+        template: None,
+        alphabet: None,
+        settings: serde_json::json!({ "doubled": false, "limit": null }),
+        nodes: 0,
+        elapsed: 0.0,
+        finished: true,
+        stop: None,
+    };
+    let path = std::env::temp_dir().join(format!("ws5-test-{}.sock", std::process::id()));
+    let address = Address::from_str(&format!("unix:{}", path.display())).unwrap();
+    assert_eq!(address, Address::Unix(path.clone()));
+    let start = |limit: Option<usize>| {
+        let plan = Plan {
+            words: words.iter().map(|w| w.as_string()).collect(),
+            metadata: metadata.clone(),
+            limit,
+            unit_timeout: Duration::from_millis(500),
+        };
+        let listener = address.listen().unwrap();
+        std::thread::spawn(move || coordinate(listener, plan))
+    };
+    let ready = || Report::Ready { metadata: Box::new(metadata.clone()) };
+    let coordinator = start(None);
+
+    // A worker that leaves without finishing its unit.
+    let (mut input, mut output) = address.connect().unwrap();
+    send(&mut output, &ready()).unwrap();
+    let assignment: Option<Assignment> = receive(&mut input).unwrap();
+    assert!(matches!(assignment, Some(Assignment::Work { id: 0, .. })));
+    drop((input, output));
+
+    // A worker that hangs on its unit, until it is dropped.
+    let (mut hung, mut hung_output) = address.connect().unwrap();
+    send(&mut hung_output, &ready()).unwrap();
+    let assignment: Option<Assignment> = receive(&mut hung).unwrap();
+    assert!(matches!(assignment, Some(Assignment::Work { .. })));
+    std::thread::sleep(Duration::from_millis(800));
+
+    // A worker with another dictionary is turned away.
+    let mut other = metadata.clone();
+    other.dict.hash = "0".repeat(16);
+    let rejected = work(address.connect().unwrap(), &dicts, other, || Ok(AppState::default()));
+    assert!(rejected.is_err());
+
+    // So is a worker that reports without saying it is
+    // ready, or on a unit it was not given.
+    let skipped = Report::Result { id: 0, squares: Vec::new(), nodes: 0 };
+    let (mut input, mut output) = address.connect().unwrap();
+    send(&mut output, &skipped).unwrap();
+    let assignment: Option<Assignment> = receive(&mut input).unwrap();
+    assert!(matches!(assignment, Some(Assignment::Rejected { .. })));
+    let (mut input, mut output) = address.connect().unwrap();
+    send(&mut output, &ready()).unwrap();
+    let assignment: Option<Assignment> = receive(&mut input).unwrap();
+    let Some(Assignment::Work { id, .. }) = assignment else { panic!() };
+    let wrong = Report::Result { id: id + 1, squares: Vec::new(), nodes: 0 };
+    send(&mut output, &wrong).unwrap();
+    let assignment: Option<Assignment> = receive(&mut input).unwrap();
+    assert!(matches!(assignment, Some(Assignment::Rejected { .. })));
+
+    let connection = address.connect().unwrap();
+    let units = work(connection, &dicts, metadata.clone(), || Ok(AppState::default())).unwrap();
+    let (squares, _, stop) = coordinator.join().unwrap();
+    drop((hung, hung_output));
+    // The coordinator has stopped listening.
+    assert!(address.connect().is_err());
+    assert_eq!(units, dicts.get(0).into_iter().count());
+    assert_eq!(squares, whole);
+    assert_eq!(stop, None);
+
+    // A limit is on the whole run.
+    let coordinator = start(Some(3));
+    let connection = address.connect().unwrap();
+    work(connection, &dicts, metadata.clone(), || Ok(AppState::default())).unwrap();
+    let (squares, _, stop) = coordinator.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(squares, whole[..3]);
+    assert_eq!(stop, Some(Stop::Limit));
+}